@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use super::BencodeValue;
+
+/// A bencode value that borrows byte strings from the input it was parsed from.
+///
+/// Every value records its `span`, the byte range it occupies in the original input, so
+/// callers can recover the exact encoded bytes with [`BencodeRef::raw`] (e.g. to hash the
+/// `info` dictionary of a torrent exactly as it was written).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BencodeRef<'a> {
+    pub kind: BencodeRefKind<'a>,
+    pub span: Range<usize>,
+    raw: &'a [u8],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BencodeRefKind<'a> {
+    Int(i64),
+    /// An integer outside the `i64` range or written non-canonically (leading zeros, `-0`),
+    /// as its literal in the input.
+    BigInt(&'a str),
+    Bytes(&'a [u8]),
+    List(Vec<BencodeRef<'a>>),
+    /// Dictionary entries in input order, including any duplicate keys.
+    Dict(Vec<(&'a [u8], BencodeRef<'a>)>),
+}
+
+impl<'a> BencodeRef<'a> {
+    pub(crate) fn new(kind: BencodeRefKind<'a>, span: Range<usize>, input: &'a [u8]) -> Self {
+        let raw = &input[span.clone()];
+        Self { kind, span, raw }
+    }
+
+    /// The exact bytes this value was parsed from.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            BencodeRefKind::Int(_) | BencodeRefKind::BigInt(_) => "integer",
+            BencodeRefKind::Bytes(_) => "byte string",
+            BencodeRefKind::List(_) => "list",
+            BencodeRefKind::Dict(_) => "dictionary",
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self.kind {
+            BencodeRefKind::Int(n) => Some(n),
+            BencodeRefKind::BigInt(digits) => digits.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Integer value, if it is non-negative.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.kind {
+            BencodeRefKind::BigInt(digits) => digits
+                .parse()
+                .ok()
+                .or_else(|| self.as_int().and_then(|n| u64::try_from(n).ok())),
+            _ => self.as_int().and_then(|n| u64::try_from(n).ok()),
+        }
+    }
+
+    /// Literal of an integer kept verbatim (see [`BencodeRefKind::BigInt`]).
+    pub fn as_big_int(&self) -> Option<&'a str> {
+        match self.kind {
+            BencodeRefKind::BigInt(digits) => Some(digits),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self.kind {
+            BencodeRefKind::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Byte string value, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&'a str> {
+        self.as_bytes().and_then(|b| std::str::from_utf8(b).ok())
+    }
+
+    pub fn as_list(&self) -> Option<&[BencodeRef<'a>]> {
+        match &self.kind {
+            BencodeRefKind::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&[(&'a [u8], BencodeRef<'a>)]> {
+        match &self.kind {
+            BencodeRefKind::Dict(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Look up a dictionary entry. When a key is duplicated the last occurrence wins, which
+    /// matches what [`BencodeValue`] keeps.
+    pub fn get<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> Option<&BencodeRef<'a>> {
+        let key = key.as_ref();
+        self.as_dict()?
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Copy into an owned [`BencodeValue`].
+    pub fn to_value(&self) -> BencodeValue {
+        match &self.kind {
+            BencodeRefKind::Int(n) => BencodeValue::Int(*n),
+            BencodeRefKind::BigInt(digits) => BencodeValue::BigInt(digits.to_string()),
+            BencodeRefKind::Bytes(bytes) => BencodeValue::Bytes(bytes.to_vec()),
+            BencodeRefKind::List(items) => {
+                BencodeValue::List(items.iter().map(BencodeRef::to_value).collect())
+            }
+            BencodeRefKind::Dict(entries) => BencodeValue::Dict(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.to_vec(), v.to_value()))
+                    .collect::<BTreeMap<_, _>>(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bencode::parse_ref;
+
+    #[test]
+    fn records_spans_and_raw_bytes() {
+        let input = b"d4:infod6:lengthi3ee4:name3:abce";
+        let root = parse_ref(input).unwrap();
+        assert_eq!(root.span, 0..input.len());
+
+        let info = root.get("info").unwrap();
+        assert_eq!(info.span, 7..20);
+        assert_eq!(info.raw(), b"d6:lengthi3ee");
+        assert_eq!(info.get("length").unwrap().raw(), b"i3e");
+
+        let name = root.get("name").unwrap();
+        assert_eq!(name.as_bytes(), Some(&b"abc"[..]));
+        assert_eq!(name.span, 26..31);
+    }
+
+    #[test]
+    fn borrows_byte_strings_from_input() {
+        let input = b"l3:abc2:\xFF\x00e".to_vec();
+        let list = parse_ref(&input).unwrap();
+        let items = list.as_list().unwrap();
+
+        let first = items[0].as_bytes().unwrap();
+        assert_eq!(first.as_ptr(), input[3..].as_ptr());
+        assert_eq!(items[1].as_bytes(), Some(&[0xFF, 0x00][..]));
+    }
+
+    #[test]
+    fn keeps_duplicate_keys_in_input_order() {
+        let dict = parse_ref(b"d1:bi1e1:ai2e1:bi3ee").unwrap();
+        let keys: Vec<&[u8]> = dict.as_dict().unwrap().iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![&b"b"[..], b"a", b"b"]);
+        assert_eq!(dict.get("b").and_then(|v| v.as_int()), Some(3));
+        assert_eq!(dict.to_value().encode(), b"d1:ai2e1:bi3ee");
+    }
+}
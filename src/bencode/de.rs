@@ -0,0 +1,419 @@
+use std::collections::HashSet;
+
+use serde::de::{
+    self, value::BorrowedBytesDeserializer, value::BorrowedStrDeserializer, DeserializeSeed,
+    IntoDeserializer, Visitor,
+};
+use serde::Deserialize;
+
+use super::{BencodeError, BencodeParser, BencodeRef, BencodeRefKind, DecodeLimits};
+
+/// Deserialize `T` from a complete bencoded buffer. Trailing bytes are an error.
+pub fn from_bytes<'de, T: Deserialize<'de>>(input: &'de [u8]) -> Result<T, BencodeError> {
+    from_bytes_limited(input, DecodeLimits::unlimited())
+}
+
+/// Like [`from_bytes`], enforcing `limits` on untrusted input.
+pub fn from_bytes_limited<'de, T: Deserialize<'de>>(
+    input: &'de [u8],
+    limits: DecodeLimits,
+) -> Result<T, BencodeError> {
+    let mut parser = BencodeParser::new(input).limits(limits);
+    let value = parser.parse_ref()?;
+    parser.ensure_consumed()?;
+    from_ref(&value)
+}
+
+/// Deserialize `T` from the first bencoded value in `input`, returning it together with the
+/// bytes that follow it (e.g. the raw piece data after a ut_metadata header dictionary).
+pub fn from_bytes_prefix<'de, T: Deserialize<'de>>(
+    input: &'de [u8],
+) -> Result<(T, &'de [u8]), BencodeError> {
+    from_bytes_prefix_limited(input, DecodeLimits::unlimited())
+}
+
+/// Like [`from_bytes_prefix`], enforcing `limits` on untrusted input.
+pub fn from_bytes_prefix_limited<'de, T: Deserialize<'de>>(
+    input: &'de [u8],
+    limits: DecodeLimits,
+) -> Result<(T, &'de [u8]), BencodeError> {
+    let mut parser = BencodeParser::new(input).limits(limits);
+    let value = parser.parse_ref()?;
+    let rest = &input[parser.position()..];
+    Ok((from_ref(&value)?, rest))
+}
+
+/// Deserialize `T` from an already parsed value.
+pub fn from_ref<'de, T: Deserialize<'de>>(value: &BencodeRef<'de>) -> Result<T, BencodeError> {
+    T::deserialize(RefDeserializer { value })
+}
+
+/// Serde adapter over a parsed [`BencodeRef`].
+///
+/// Integers map to `i64`, byte strings to borrowed bytes (or `&str` when a string is asked
+/// for), lists to sequences and dictionaries to maps/structs. Integers `0` and `1` also
+/// deserialize as `bool`.
+struct RefDeserializer<'a, 'de> {
+    value: &'a BencodeRef<'de>,
+}
+
+impl<'a, 'de> RefDeserializer<'a, 'de> {
+    fn offset(&self) -> usize {
+        self.value.span.start
+    }
+}
+
+impl<'a, 'de> de::Deserializer<'de> for RefDeserializer<'a, 'de> {
+    type Error = BencodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodeError> {
+        let offset = self.offset();
+        let result = match &self.value.kind {
+            BencodeRefKind::Int(n) => visitor.visit_i64(*n),
+            BencodeRefKind::BigInt(digits) => {
+                if let Ok(n) = digits.parse::<u64>() {
+                    visitor.visit_u64(n)
+                } else if let Ok(n) = digits.parse::<i128>() {
+                    visitor.visit_i128(n)
+                } else if let Ok(n) = digits.parse::<u128>() {
+                    visitor.visit_u128(n)
+                } else {
+                    visitor.visit_borrowed_str(digits)
+                }
+            }
+            BencodeRefKind::Bytes(bytes) => visitor.visit_borrowed_bytes(bytes),
+            BencodeRefKind::List(items) => visitor.visit_seq(SeqAccess {
+                items: items.iter(),
+            }),
+            BencodeRefKind::Dict(entries) => visitor.visit_map(MapAccess::new(entries)),
+        };
+        result.map_err(|e| e.at_offset(offset))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodeError> {
+        match self.value.kind {
+            BencodeRefKind::Int(0) => visitor.visit_bool(false),
+            BencodeRefKind::Int(1) => visitor.visit_bool(true),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodeError> {
+        let offset = self.offset();
+        match self.value.as_str() {
+            Some(s) => visitor
+                .visit_borrowed_str::<BencodeError>(s)
+                .map_err(|e| e.at_offset(offset)),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodeError> {
+        // Bencode has no null; absent dictionary keys are handled by serde as `None`.
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, BencodeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, BencodeError> {
+        let offset = self.offset();
+        let result = match &self.value.kind {
+            // Unit variants are written as their name.
+            BencodeRefKind::Bytes(_) => {
+                let name = self
+                    .value
+                    .as_str()
+                    .ok_or_else(|| de::Error::custom("enum variant name must be valid UTF-8"))?;
+                visitor.visit_enum(name.into_deserializer())
+            }
+            // Other variants are a single-entry dictionary `{variant: content}`.
+            BencodeRefKind::Dict(entries) if entries.len() == 1 => {
+                let (name, content) = &entries[0];
+                visitor.visit_enum(EnumAccess { name, content })
+            }
+            _ => Err(de::Error::custom(format!(
+                "expected an enum variant name or single-key dictionary, found {}",
+                self.value.type_name()
+            ))),
+        };
+        result.map_err(|e| e.at_offset(offset))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, BencodeError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct
+    }
+}
+
+struct SeqAccess<'a, 'de> {
+    items: std::slice::Iter<'a, BencodeRef<'de>>,
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for SeqAccess<'a, 'de> {
+    type Error = BencodeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, BencodeError> {
+        match self.items.next() {
+            Some(value) => seed.deserialize(RefDeserializer { value }).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapAccess<'a, 'de> {
+    entries: std::vec::IntoIter<&'a (&'de [u8], BencodeRef<'de>)>,
+    pending_value: Option<&'a BencodeRef<'de>>,
+}
+
+impl<'a, 'de> MapAccess<'a, 'de> {
+    fn new(entries: &'a [(&'de [u8], BencodeRef<'de>)]) -> Self {
+        // Lenient input may repeat a key; keep only the last occurrence so that derived
+        // structs see the same data as `BencodeValue`.
+        let mut seen = HashSet::new();
+        let mut unique: Vec<_> = entries
+            .iter()
+            .rev()
+            .filter(|(k, _)| seen.insert(*k))
+            .collect();
+        unique.reverse();
+
+        Self {
+            entries: unique.into_iter(),
+            pending_value: None,
+        }
+    }
+}
+
+impl<'a, 'de> de::MapAccess<'de> for MapAccess<'a, 'de> {
+    type Error = BencodeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, BencodeError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.pending_value = Some(value);
+                seed.deserialize(KeyDeserializer(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, BencodeError> {
+        let value = self
+            .pending_value
+            .take()
+            .ok_or_else(|| de::Error::custom("map value requested before its key"))?;
+        seed.deserialize(RefDeserializer { value })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+/// Dictionary keys are byte strings; expose them as `&str` when they are valid UTF-8.
+struct KeyDeserializer<'de>(&'de [u8]);
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer<'de> {
+    type Error = BencodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, BencodeError> {
+        match std::str::from_utf8(self.0) {
+            Ok(s) => BorrowedStrDeserializer::new(s).deserialize_any(visitor),
+            Err(_) => BorrowedBytesDeserializer::new(self.0).deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+        identifier ignored_any
+    }
+}
+
+struct EnumAccess<'a, 'de> {
+    name: &'de [u8],
+    content: &'a BencodeRef<'de>,
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for EnumAccess<'a, 'de> {
+    type Error = BencodeError;
+    type Variant = RefDeserializer<'a, 'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), BencodeError> {
+        let variant = seed.deserialize(KeyDeserializer(self.name))?;
+        Ok((
+            variant,
+            RefDeserializer {
+                value: self.content,
+            },
+        ))
+    }
+}
+
+impl<'a, 'de> de::VariantAccess<'de> for RefDeserializer<'a, 'de> {
+    type Error = BencodeError;
+
+    fn unit_variant(self) -> Result<(), BencodeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, BencodeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, BencodeError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, BencodeError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Info<'a> {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u64,
+        #[serde(with = "serde_bytes")]
+        pieces: Vec<u8>,
+        comment: Option<&'a str>,
+        private: Option<bool>,
+    }
+
+    #[test]
+    fn deserializes_structs_with_bytes_and_options() {
+        let input = b"d4:name3:abc12:piece lengthi16384e6:pieces2:\xFF\x007:privatei1ee";
+        let info: Info = from_bytes(input).unwrap();
+        assert_eq!(
+            info,
+            Info {
+                name: "abc".to_string(),
+                piece_length: 16384,
+                pieces: vec![0xFF, 0x00],
+                comment: None,
+                private: Some(true),
+            }
+        );
+    }
+
+    #[test]
+    fn deserializes_maps_and_lists() {
+        let map: BTreeMap<String, Vec<i64>> = from_bytes(b"d1:ali1ei2ee1:blee").unwrap();
+        assert_eq!(map["a"], vec![1, 2]);
+        assert!(map["b"].is_empty());
+    }
+
+    #[test]
+    fn deserializes_integers_beyond_i64() {
+        let sizes: Vec<u64> = from_bytes(b"li18446744073709551615ei7ee").unwrap();
+        assert_eq!(sizes, vec![u64::MAX, 7]);
+        let big: i128 = from_bytes(b"i-9223372036854775809e").unwrap();
+        assert_eq!(big, -9223372036854775809);
+        assert!(from_bytes::<i64>(b"i9223372036854775808e").is_err());
+    }
+
+    #[test]
+    fn from_bytes_prefix_returns_trailing_bytes() {
+        #[derive(Deserialize)]
+        struct Address {
+            city: String,
+            street: String,
+        }
+
+        let (decoded, rest): (Address, _) =
+            from_bytes_prefix(b"d4:city4:test6:street4:teste_kekekekek").unwrap();
+        assert_eq!(decoded.city, "test");
+        assert_eq!(decoded.street, "test");
+        assert_eq!(rest, b"_kekekekek");
+
+        assert!(from_bytes::<Address>(b"d4:city4:test6:street4:teste_").is_err());
+    }
+
+    #[test]
+    fn reports_type_errors_with_value_offset() {
+        #[derive(Debug, Deserialize)]
+        struct Lengths {
+            #[allow(dead_code)]
+            length: u64,
+        }
+
+        let err = from_bytes::<Lengths>(b"d6:length3:abce").unwrap_err();
+        assert_eq!(err.offset(), Some(9));
+        assert!(err.to_string().contains("invalid type"), "{err}");
+    }
+
+    #[test]
+    fn deserializes_enums() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Event {
+            Started,
+            Stopped { at: u32 },
+        }
+
+        assert_eq!(from_bytes::<Event>(b"7:Started").unwrap(), Event::Started);
+        assert_eq!(
+            from_bytes::<Event>(b"d7:Stoppedd2:ati5eee").unwrap(),
+            Event::Stopped { at: 5 }
+        );
+    }
+}
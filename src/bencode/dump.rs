@@ -0,0 +1,78 @@
+use std::fmt::Write;
+
+use super::{BencodeRef, BencodeRefKind};
+
+/// Render a parsed value as an indented listing, one value per line, prefixed with the hex
+/// byte offset at which it starts. Byte strings that are not printable UTF-8 are shown as hex.
+pub fn dump(value: &BencodeRef<'_>) -> String {
+    let mut out = String::new();
+    dump_value(value, None, 0, &mut out);
+    out
+}
+
+fn dump_value(value: &BencodeRef<'_>, key: Option<&[u8]>, depth: usize, out: &mut String) {
+    let label = match key {
+        Some(key) => format!("{}: ", describe_bytes(key)),
+        None => String::new(),
+    };
+    let description = match &value.kind {
+        BencodeRefKind::Int(n) => n.to_string(),
+        BencodeRefKind::BigInt(digits) => digits.to_string(),
+        BencodeRefKind::Bytes(bytes) => describe_bytes(bytes),
+        BencodeRefKind::List(items) => format!("list ({} items)", items.len()),
+        BencodeRefKind::Dict(entries) => format!("dict ({} entries)", entries.len()),
+    };
+    let _ = writeln!(
+        out,
+        "{:08x}  {}{}{}",
+        value.span.start,
+        "  ".repeat(depth),
+        label,
+        description
+    );
+
+    match &value.kind {
+        BencodeRefKind::List(items) => {
+            for item in items {
+                dump_value(item, None, depth + 1, out);
+            }
+        }
+        BencodeRefKind::Dict(entries) => {
+            for (key, item) in entries {
+                dump_value(item, Some(key), depth + 1, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn describe_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.chars().any(char::is_control) => format!("{:?}", s),
+        _ => format!("<{} bytes> {}", bytes.len(), hex::encode(bytes)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::parse_ref;
+
+    #[test]
+    fn dumps_offsets_keys_and_binary_as_hex() {
+        let value = parse_ref(b"d4:infod6:pieces2:\xAB\xCDe4:listli7e1:xee").unwrap();
+        assert_eq!(
+            dump(&value),
+            [
+                "00000000  dict (2 entries)",
+                "00000007    \"info\": dict (1 entries)",
+                "00000010      \"pieces\": <2 bytes> abcd",
+                "0000001b    \"list\": list (2 items)",
+                "0000001c      7",
+                "0000001f      \"x\"",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
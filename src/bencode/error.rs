@@ -0,0 +1,139 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+
+/// Number of bytes shown on either side of the failing offset in error messages.
+const CONTEXT_RADIUS: usize = 12;
+
+/// Errors produced while decoding or encoding bencode.
+///
+/// Syntax errors record the byte `offset` at which decoding failed, a description of the
+/// token that was `expected` there and an escaped `context` snippet of the surrounding input.
+/// [`BencodeError::Serde`] carries errors raised while mapping values onto Rust types.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BencodeError {
+    #[error("unexpected end of input at byte {offset}: expected {expected} (near `{context}`)")]
+    UnexpectedEof {
+        offset: usize,
+        expected: &'static str,
+        context: String,
+    },
+
+    #[error("unexpected byte '{found}' at byte {offset}: expected {expected} (near `{context}`)")]
+    UnexpectedByte {
+        offset: usize,
+        expected: &'static str,
+        found: String,
+        context: String,
+    },
+
+    #[error("invalid string length at byte {offset}: expected {expected} (near `{context}`)")]
+    InvalidLength {
+        offset: usize,
+        expected: &'static str,
+        context: String,
+    },
+
+    #[error("non-canonical encoding at byte {offset}: expected {expected} (near `{context}`)")]
+    NonCanonical {
+        offset: usize,
+        expected: &'static str,
+        context: String,
+    },
+
+    #[error(
+        "decoding limit exceeded at byte {offset}: {limit} is limited to {max} (near `{context}`)"
+    )]
+    LimitExceeded {
+        offset: usize,
+        limit: &'static str,
+        max: usize,
+        context: String,
+    },
+
+    #[error("trailing data at byte {offset}: expected end of input (near `{context}`)")]
+    TrailingData { offset: usize, context: String },
+
+    #[error("{message}{}", .offset.map(|o| format!(" (value at byte {o})")).unwrap_or_default())]
+    Serde {
+        offset: Option<usize>,
+        message: String,
+    },
+}
+
+impl BencodeError {
+    /// Byte offset in the input at which decoding failed, if known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            BencodeError::UnexpectedEof { offset, .. }
+            | BencodeError::UnexpectedByte { offset, .. }
+            | BencodeError::InvalidLength { offset, .. }
+            | BencodeError::NonCanonical { offset, .. }
+            | BencodeError::LimitExceeded { offset, .. }
+            | BencodeError::TrailingData { offset, .. } => Some(*offset),
+            BencodeError::Serde { offset, .. } => *offset,
+        }
+    }
+
+    /// Attach the offset of the value being deserialized to a serde error that has none yet.
+    pub(crate) fn at_offset(self, at: usize) -> Self {
+        match self {
+            BencodeError::Serde {
+                offset: None,
+                message,
+            } => BencodeError::Serde {
+                offset: Some(at),
+                message,
+            },
+            other => other,
+        }
+    }
+}
+
+impl serde::de::Error for BencodeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        BencodeError::Serde {
+            offset: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl serde::ser::Error for BencodeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        BencodeError::Serde {
+            offset: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+/// Render the input around `offset` as an escaped snippet, marking the offset with `>>`.
+pub(crate) fn context_snippet(input: &[u8], offset: usize) -> String {
+    let offset = offset.min(input.len());
+    let start = offset.saturating_sub(CONTEXT_RADIUS);
+    let end = (offset + CONTEXT_RADIUS).min(input.len());
+
+    format!(
+        "{}{}>>{}{}",
+        if start > 0 { "..." } else { "" },
+        input[start..offset].escape_ascii(),
+        input[offset..end].escape_ascii(),
+        if end < input.len() { "..." } else { "" },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_snippet_marks_offset_and_truncates() {
+        assert_eq!(context_snippet(b"i42x", 3), "i42>>x");
+        assert_eq!(
+            context_snippet(b"d8:announce35:http://tracker.example/announcee", 14),
+            "...:announce35:>>http://track..."
+        );
+        assert_eq!(context_snippet(b"\xFF:", 0), ">>\\xff:");
+    }
+}
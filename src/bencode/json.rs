@@ -0,0 +1,336 @@
+//! Lossless mapping between bencode and JSON.
+//!
+//! Integers become JSON numbers and UTF-8 byte strings become JSON strings. Everything JSON
+//! cannot hold directly is wrapped in a single-key marker object:
+//!
+//! - `{"$hex": "ff00"}` / `{"$base64": "/wA="}`: a byte string that is not valid UTF-8.
+//! - `{"$int": "-9223372036854775809"}`: an integer that JSON numbers here cannot hold
+//!   exactly (below `i64::MIN`, above `u64::MAX`, or written non-canonically, e.g. with
+//!   leading zeros or as `-0`).
+//! - `{"$dict": [[key, value], ...]}`: a dictionary with a non-UTF-8 key, with keys that are
+//!   unsorted or repeated, or whose only key starts with `$` and would otherwise be mistaken
+//!   for a marker.
+//!
+//! [`from_json`] reverses [`to_json`], so `from_json(&to_json(v, _))` always equals `v`.
+//! [`ref_to_json`] also keeps the key order of the input, and [`encode_json`] writes it back,
+//! so `encode_json(&ref_to_json(v, _))` reproduces the original bytes exactly.
+
+use std::collections::BTreeMap;
+
+use data_encoding::BASE64;
+use serde::de::Error as _;
+use serde_json::{json, Map, Value};
+
+use super::{BencodeError, BencodeRef, BencodeRefKind, BencodeValue};
+
+const HEX_MARKER: &str = "$hex";
+const BASE64_MARKER: &str = "$base64";
+const DICT_MARKER: &str = "$dict";
+const INT_MARKER: &str = "$int";
+
+/// How byte strings that are not valid UTF-8 are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+impl std::str::FromStr for BinaryEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(BinaryEncoding::Hex),
+            "base64" => Ok(BinaryEncoding::Base64),
+            other => anyhow::bail!("unknown binary encoding '{other}' (expected hex or base64)"),
+        }
+    }
+}
+
+/// Convert a value to JSON without losing information.
+pub fn to_json(value: &BencodeValue, binary: BinaryEncoding) -> Value {
+    match value {
+        BencodeValue::Int(n) => Value::from(*n),
+        BencodeValue::BigInt(digits) => big_int_to_json(digits),
+        BencodeValue::Bytes(bytes) => bytes_to_json(bytes, binary),
+        BencodeValue::List(items) => {
+            Value::Array(items.iter().map(|item| to_json(item, binary)).collect())
+        }
+        BencodeValue::Dict(map) => dict_to_json(
+            map.iter()
+                .map(|(k, v)| (k.as_slice(), to_json(v, binary)))
+                .collect(),
+            binary,
+        ),
+    }
+}
+
+/// Convert a parsed value to JSON, keeping dictionaries in input order.
+///
+/// Unlike [`to_json`] this also preserves unsorted and duplicate keys, so
+/// `encode_json(&ref_to_json(v, _))` reproduces `v.raw()` byte for byte.
+pub fn ref_to_json(value: &BencodeRef<'_>, binary: BinaryEncoding) -> Value {
+    match &value.kind {
+        BencodeRefKind::Int(n) => Value::from(*n),
+        BencodeRefKind::BigInt(digits) => big_int_to_json(digits),
+        BencodeRefKind::Bytes(bytes) => bytes_to_json(bytes, binary),
+        BencodeRefKind::List(items) => {
+            Value::Array(items.iter().map(|item| ref_to_json(item, binary)).collect())
+        }
+        BencodeRefKind::Dict(entries) => dict_to_json(
+            entries
+                .iter()
+                .map(|(k, v)| (*k, ref_to_json(v, binary)))
+                .collect(),
+            binary,
+        ),
+    }
+}
+
+fn big_int_to_json(digits: &str) -> Value {
+    match digits.parse::<u64>() {
+        Ok(n) if n.to_string() == digits => Value::from(n),
+        _ => json!({ INT_MARKER: digits }),
+    }
+}
+
+/// A plain JSON object when the keys are sorted, unique UTF-8 strings that cannot be
+/// mistaken for a marker; a `$dict` entry list otherwise.
+fn dict_to_json(entries: Vec<(&[u8], Value)>, binary: BinaryEncoding) -> Value {
+    let sorted = entries.windows(2).all(|pair| pair[0].0 < pair[1].0);
+    let keys: Option<Vec<&str>> = entries
+        .iter()
+        .map(|(k, _)| std::str::from_utf8(k).ok())
+        .collect();
+    match keys {
+        Some(keys) if sorted && !(keys.len() == 1 && keys[0].starts_with('$')) => Value::Object(
+            keys.into_iter()
+                .zip(entries)
+                .map(|(k, (_, v))| (k.to_string(), v))
+                .collect(),
+        ),
+        _ => {
+            let entries = entries
+                .into_iter()
+                .map(|(k, v)| json!([bytes_to_json(k, binary), v]))
+                .collect();
+            json!({ DICT_MARKER: Value::Array(entries) })
+        }
+    }
+}
+
+fn bytes_to_json(bytes: &[u8], binary: BinaryEncoding) -> Value {
+    match (std::str::from_utf8(bytes), binary) {
+        (Ok(s), _) => Value::String(s.to_string()),
+        (Err(_), BinaryEncoding::Hex) => json!({ HEX_MARKER: hex::encode(bytes) }),
+        (Err(_), BinaryEncoding::Base64) => json!({ BASE64_MARKER: BASE64.encode(bytes) }),
+    }
+}
+
+/// Convert JSON produced by [`to_json`] (or written by hand) back into a bencode value.
+///
+/// Booleans are accepted as `0`/`1`; floats and `null` have no bencode form and are errors.
+pub fn from_json(json: &Value) -> Result<BencodeValue, BencodeError> {
+    match json {
+        Value::Null => Err(BencodeError::custom("null has no bencode representation")),
+        Value::Bool(b) => Ok(BencodeValue::from(*b)),
+        Value::Number(n) if n.is_f64() => {
+            Err(BencodeError::custom(format!("{n} is not an integer")))
+        }
+        Value::Number(n) => Ok(BencodeValue::from_digits(&n.to_string())
+            .expect("JSON integers are plain digit strings")),
+        Value::String(s) => Ok(BencodeValue::from(s.as_str())),
+        Value::Array(items) => Ok(BencodeValue::List(
+            items.iter().map(from_json).collect::<Result<_, _>>()?,
+        )),
+        Value::Object(map) => match single_marker(map) {
+            Some((HEX_MARKER, Value::String(s))) => hex::decode(s)
+                .map(BencodeValue::Bytes)
+                .map_err(|e| BencodeError::custom(format!("invalid {HEX_MARKER} value: {e}"))),
+            Some((BASE64_MARKER, Value::String(s))) => BASE64
+                .decode(s.as_bytes())
+                .map(BencodeValue::Bytes)
+                .map_err(|e| BencodeError::custom(format!("invalid {BASE64_MARKER} value: {e}"))),
+            Some((INT_MARKER, Value::String(s))) => BencodeValue::from_digits(s)
+                .ok_or_else(|| BencodeError::custom(format!("invalid {INT_MARKER} value: {s}"))),
+            Some((DICT_MARKER, Value::Array(entries))) => dict_from_entries(entries),
+            Some((marker, _)) => Err(BencodeError::custom(format!(
+                "invalid value for marker {marker}"
+            ))),
+            None => Ok(BencodeValue::Dict(
+                map.iter()
+                    .map(|(k, v)| Ok((k.as_bytes().to_vec(), from_json(v)?)))
+                    .collect::<Result<_, BencodeError>>()?,
+            )),
+        },
+    }
+}
+
+/// Encode JSON produced by [`ref_to_json`] (or [`to_json`]) straight to bencode.
+///
+/// `$dict` entries are written in the order given, duplicates included; plain objects are
+/// written with sorted keys. Everything else is encoded as [`from_json`] would.
+pub fn encode_json(json: &Value) -> Result<Vec<u8>, BencodeError> {
+    let mut out = Vec::new();
+    encode_json_into(json, &mut out)?;
+    Ok(out)
+}
+
+fn encode_json_into(json: &Value, out: &mut Vec<u8>) -> Result<(), BencodeError> {
+    match json {
+        Value::Array(items) => {
+            out.push(b'l');
+            for item in items {
+                encode_json_into(item, out)?;
+            }
+            out.push(b'e');
+        }
+        Value::Object(map) => match single_marker(map) {
+            Some((DICT_MARKER, Value::Array(entries))) => {
+                out.push(b'd');
+                for (key, value) in dict_entries(entries)? {
+                    out.extend_from_slice(&BencodeValue::Bytes(key).encode());
+                    encode_json_into(value, out)?;
+                }
+                out.push(b'e');
+            }
+            Some(_) => out.extend_from_slice(&from_json(json)?.encode()),
+            None => {
+                out.push(b'd');
+                for (key, value) in map {
+                    out.extend_from_slice(&BencodeValue::from(key.as_str()).encode());
+                    encode_json_into(value, out)?;
+                }
+                out.push(b'e');
+            }
+        },
+        _ => out.extend_from_slice(&from_json(json)?.encode()),
+    }
+    Ok(())
+}
+
+fn single_marker(map: &Map<String, Value>) -> Option<(&str, &Value)> {
+    if map.len() != 1 {
+        return None;
+    }
+    let (key, value) = map.iter().next()?;
+    [HEX_MARKER, BASE64_MARKER, INT_MARKER, DICT_MARKER]
+        .into_iter()
+        .find(|marker| marker == key)
+        .map(|marker| (marker, value))
+}
+
+fn dict_from_entries(entries: &[Value]) -> Result<BencodeValue, BencodeError> {
+    let mut map = BTreeMap::new();
+    for (key, value) in dict_entries(entries)? {
+        map.insert(key, from_json(value)?);
+    }
+    Ok(BencodeValue::Dict(map))
+}
+
+/// The `[key, value]` pairs of a `$dict` marker, with keys decoded to bytes.
+fn dict_entries(entries: &[Value]) -> Result<Vec<(Vec<u8>, &Value)>, BencodeError> {
+    entries
+        .iter()
+        .map(|entry| {
+            let (key, value) = match entry.as_array().map(Vec::as_slice) {
+                Some([key, value]) => (key, value),
+                _ => {
+                    return Err(BencodeError::custom(format!(
+                        "{DICT_MARKER} entries must be [key, value] pairs"
+                    )))
+                }
+            };
+            let BencodeValue::Bytes(key) = from_json(key)? else {
+                return Err(BencodeError::custom(format!(
+                    "{DICT_MARKER} keys must be strings or byte markers"
+                )));
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::{parse_bytes, parse_ref};
+
+    fn round_trip(input: &[u8], binary: BinaryEncoding) -> Value {
+        let value = parse_bytes(input.to_vec()).unwrap();
+        let json = to_json(&value, binary);
+        let text = json.to_string();
+        let reparsed: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(from_json(&reparsed).unwrap().encode(), input);
+        json
+    }
+
+    #[test]
+    fn text_values_map_to_plain_json() {
+        let json = round_trip(b"d3:bar4:spam3:fooli42ei-1eee", BinaryEncoding::Hex);
+        assert_eq!(json, json!({"bar": "spam", "foo": [42, -1]}));
+    }
+
+    #[test]
+    fn binary_strings_use_markers() {
+        let json = round_trip(b"d6:pieces3:\xFF\x00\x01e", BinaryEncoding::Hex);
+        assert_eq!(json, json!({"pieces": {"$hex": "ff0001"}}));
+
+        let json = round_trip(b"d6:pieces3:\xFF\x00\x01e", BinaryEncoding::Base64);
+        assert_eq!(json, json!({"pieces": {"$base64": "/wAB"}}));
+    }
+
+    #[test]
+    fn large_integers_survive_the_round_trip() {
+        let json = round_trip(
+            b"li18446744073709551615ei18446744073709551616ee",
+            BinaryEncoding::Hex,
+        );
+        assert_eq!(
+            json,
+            json!([18446744073709551615u64, {"$int": "18446744073709551616"}])
+        );
+        round_trip(b"i-9223372036854775809e", BinaryEncoding::Hex);
+        round_trip(b"i000099999999999999999999e", BinaryEncoding::Hex);
+        assert!(from_json(&json!({"$int": "12a"})).is_err());
+    }
+
+    #[test]
+    fn dictionaries_that_json_cannot_hold_use_entry_lists() {
+        let json = round_trip(b"d1:a1:b2:\xFF\xFFi1ee", BinaryEncoding::Hex);
+        assert_eq!(json, json!({"$dict": [["a", "b"], [{"$hex": "ffff"}, 1]]}));
+
+        let json = round_trip(b"d4:$hex2:ffe", BinaryEncoding::Hex);
+        assert_eq!(json, json!({"$dict": [["$hex", "ff"]]}));
+    }
+
+    #[test]
+    fn non_canonical_dictionaries_keep_their_key_order() {
+        for input in [
+            &b"d1:bi1e1:ai2ee"[..],
+            b"d1:ai1e1:ai2ee",
+            b"ld1:bi1e1:ai2eee",
+        ] {
+            let json = ref_to_json(&parse_ref(input).unwrap(), BinaryEncoding::Hex);
+            let reparsed: Value = serde_json::from_str(&json.to_string()).unwrap();
+            assert_eq!(encode_json(&reparsed).unwrap(), input);
+        }
+
+        let json = ref_to_json(&parse_ref(b"d1:bi1e1:ai2ee").unwrap(), BinaryEncoding::Hex);
+        assert_eq!(json, json!({"$dict": [["b", 1], ["a", 2]]}));
+
+        let json = ref_to_json(&parse_ref(b"d1:ai1e1:bi2ee").unwrap(), BinaryEncoding::Hex);
+        assert_eq!(json, json!({"a": 1, "b": 2}));
+        assert_eq!(encode_json(&json).unwrap(), b"d1:ai1e1:bi2ee");
+    }
+
+    #[test]
+    fn rejects_json_without_bencode_form() {
+        assert!(from_json(&json!(1.5)).is_err());
+        assert!(from_json(&json!(null)).is_err());
+        assert!(from_json(&json!({"$hex": "zz"})).is_err());
+        assert!(from_json(&json!({"$dict": [["a"]]})).is_err());
+        assert_eq!(from_json(&json!(true)).unwrap(), BencodeValue::Int(1));
+    }
+}
@@ -0,0 +1,45 @@
+/// Resource limits applied while decoding bencode from untrusted sources.
+///
+/// The parser checks them as it goes, so hostile input fails with
+/// [`BencodeError::LimitExceeded`](super::BencodeError::LimitExceeded) before it can
+/// overflow the stack or force a large allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Maximum nesting of lists and dictionaries.
+    pub max_depth: usize,
+    /// Maximum size in bytes of the whole input.
+    pub max_total_size: usize,
+    /// Maximum declared length of a single byte string.
+    pub max_string_len: usize,
+    /// Maximum number of list items and dictionary entries, counted across the document.
+    pub max_items: usize,
+}
+
+impl DecodeLimits {
+    /// No limits; used for local files and other trusted input.
+    pub fn unlimited() -> Self {
+        Self {
+            max_depth: usize::MAX,
+            max_total_size: usize::MAX,
+            max_string_len: usize::MAX,
+            max_items: usize::MAX,
+        }
+    }
+
+    /// Limits for data received from trackers and peers. Generous enough for the info
+    /// dictionary of a very large torrent, far below what would exhaust memory.
+    pub fn untrusted() -> Self {
+        Self {
+            max_depth: 64,
+            max_total_size: 16 * 1024 * 1024,
+            max_string_len: 8 * 1024 * 1024,
+            max_items: 1_000_000,
+        }
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
@@ -0,0 +1,74 @@
+mod borrowed;
+mod de;
+mod dump;
+mod error;
+mod json;
+mod limits;
+mod parser;
+mod path;
+mod ser;
+mod value;
+
+pub use borrowed::{BencodeRef, BencodeRefKind};
+pub use de::{
+    from_bytes, from_bytes_limited, from_bytes_prefix, from_bytes_prefix_limited, from_ref,
+};
+pub use dump::dump;
+pub use error::BencodeError;
+pub use json::{encode_json, from_json, ref_to_json, to_json, BinaryEncoding};
+pub use limits::DecodeLimits;
+pub use parser::BencodeParser;
+pub use path::{parse_path, select, PathError, PathSegment};
+pub use ser::{to_bytes, to_value};
+pub use value::BencodeValue;
+
+pub fn parse_string(encoded_value: &str) -> Result<BencodeValue, BencodeError> {
+    parse_bytes(encoded_value.as_bytes().to_vec())
+}
+
+pub fn parse_bytes(encoded_value: Vec<u8>) -> Result<BencodeValue, BencodeError> {
+    Ok(parse_ref(&encoded_value)?.to_value())
+}
+
+/// Parse a single value from untrusted input, enforcing `limits`.
+pub fn parse_bytes_limited(
+    encoded_value: Vec<u8>,
+    limits: DecodeLimits,
+) -> Result<BencodeValue, BencodeError> {
+    let mut parser = BencodeParser::new(&encoded_value).limits(limits);
+    let value = parser.parse_value()?;
+    parser.ensure_consumed()?;
+    Ok(value)
+}
+
+/// Parse a single value, rejecting any non-canonical encoding (see [`BencodeParser::strict`]).
+pub fn parse_bytes_strict(encoded_value: Vec<u8>) -> Result<BencodeValue, BencodeError> {
+    let mut parser = BencodeParser::new(&encoded_value).strict(true);
+    let value = parser.parse_value()?;
+    parser.ensure_consumed()?;
+    Ok(value)
+}
+
+/// Parse a single value that borrows from `input` and records source spans.
+pub fn parse_ref(input: &[u8]) -> Result<BencodeRef<'_>, BencodeError> {
+    let mut parser = BencodeParser::new(input);
+    let value = parser.parse_ref()?;
+    parser.ensure_consumed()?;
+    Ok(value)
+}
+
+/// Like [`parse_ref`], enforcing `limits` on untrusted input.
+pub fn parse_ref_limited(
+    input: &[u8],
+    limits: DecodeLimits,
+) -> Result<BencodeRef<'_>, BencodeError> {
+    let mut parser = BencodeParser::new(input).limits(limits);
+    let value = parser.parse_ref()?;
+    parser.ensure_consumed()?;
+    Ok(value)
+}
+
+/// Encode a value into canonical bencode.
+pub fn encode(value: &BencodeValue) -> Vec<u8> {
+    value.encode()
+}
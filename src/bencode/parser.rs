@@ -0,0 +1,572 @@
+use super::error::context_snippet;
+use super::{BencodeError, BencodeRef, BencodeRefKind, BencodeValue, DecodeLimits};
+
+/// Parser over a borrowed input buffer.
+///
+/// [`BencodeParser::parse_ref`] borrows byte strings from the input and records the span of
+/// every value; [`BencodeParser::parse_value`] copies the result into an owned [`BencodeValue`].
+pub struct BencodeParser<'a> {
+    input: &'a [u8],
+    index: usize,
+    strict: bool,
+    limits: DecodeLimits,
+    depth: usize,
+    items: usize,
+}
+
+impl<'a> BencodeParser<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            index: 0,
+            strict: false,
+            limits: DecodeLimits::unlimited(),
+            depth: 0,
+            items: 0,
+        }
+    }
+
+    /// Enforce resource limits while parsing; see [`DecodeLimits::untrusted`].
+    pub fn limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reject non-canonical encodings (BEP-3): unsorted or duplicate dictionary keys,
+    /// integers with leading zeros or `-0`, and string lengths with leading zeros.
+    ///
+    /// In the default lenient mode such input is accepted; duplicate keys keep the last value.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn parse_value(&mut self) -> Result<BencodeValue, BencodeError> {
+        Ok(self.parse_ref()?.to_value())
+    }
+
+    /// Parse the next value without copying byte strings out of the input.
+    pub fn parse_ref(&mut self) -> Result<BencodeRef<'a>, BencodeError> {
+        if self.depth == 0 && self.input.len() > self.limits.max_total_size {
+            return Err(self.limit_exceeded(0, "input size", self.limits.max_total_size));
+        }
+
+        let start = self.index;
+        let kind = match self.peek() {
+            Some(b'i') => self.parse_integer()?,
+            Some(b'l') => BencodeRefKind::List(self.parse_list()?),
+            Some(b'd') => BencodeRefKind::Dict(self.parse_dictionary()?),
+            Some(c) if c.is_ascii_digit() => BencodeRefKind::Bytes(self.parse_string()?),
+            Some(_) => {
+                return Err(self.unexpected_byte("a value ('i', 'l', 'd' or a string length)"))
+            }
+            None => return Err(self.unexpected_eof("a value")),
+        };
+        Ok(BencodeRef::new(kind, start..self.index, self.input))
+    }
+
+    fn parse_string(&mut self) -> Result<&'a [u8], BencodeError> {
+        let length_start = self.index;
+        let digits_len = self.skip_while(|b| b.is_ascii_digit());
+        let digits = &self.input[length_start..self.index];
+        if digits_len == 0 {
+            return Err(self.unexpected_byte("a string length"));
+        }
+        if self.strict && digits.len() > 1 && digits[0] == b'0' {
+            return Err(self.non_canonical(length_start, "a string length without leading zeros"));
+        }
+        let byte_length = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .ok_or_else(|| BencodeError::InvalidLength {
+                offset: length_start,
+                expected: "a length that fits in memory",
+                context: context_snippet(self.input, length_start),
+            })?;
+        if byte_length > self.limits.max_string_len {
+            return Err(self.limit_exceeded(
+                length_start,
+                "string length",
+                self.limits.max_string_len,
+            ));
+        }
+        self.expect_byte(b':', "':' after string length")?;
+
+        let end = self
+            .index
+            .checked_add(byte_length)
+            .filter(|&end| end <= self.input.len())
+            .ok_or_else(|| BencodeError::UnexpectedEof {
+                offset: self.input.len(),
+                expected: "the rest of the string data",
+                context: context_snippet(self.input, length_start),
+            })?;
+
+        let value = &self.input[self.index..end];
+        self.index = end;
+        Ok(value)
+    }
+
+    fn parse_integer(&mut self) -> Result<BencodeRefKind<'a>, BencodeError> {
+        self.expect_byte(b'i', "'i'")?;
+        let start = self.index;
+        if self.peek() == Some(b'-') {
+            self.index += 1;
+        }
+        let digits_len = self.skip_while(|b| b.is_ascii_digit());
+        if digits_len == 0 {
+            return Err(match self.peek() {
+                Some(_) => self.unexpected_byte("integer digits"),
+                None => self.unexpected_eof("integer digits"),
+            });
+        }
+        let end = self.index;
+        self.expect_byte(b'e', "'e' terminator for integer")?;
+
+        if self.strict {
+            let literal = &self.input[start..end];
+            let digits = literal.strip_prefix(b"-").unwrap_or(literal);
+            if digits.len() > 1 && digits[0] == b'0' {
+                return Err(self.non_canonical(start, "an integer without leading zeros"));
+            }
+            if literal == b"-0" {
+                return Err(self.non_canonical(start, "zero written as 'i0e', not 'i-0e'"));
+            }
+        }
+
+        // Only ASCII digits and a leading '-' were consumed, so this is valid UTF-8.
+        let input: &'a [u8] = self.input;
+        let literal = std::str::from_utf8(&input[start..end]).expect("integer digits are ASCII");
+        // Non-canonical literals (leading zeros, `-0`) stay verbatim so they re-encode as written.
+        Ok(match literal.parse::<i64>() {
+            Ok(number) if number.to_string() == literal => BencodeRefKind::Int(number),
+            _ => BencodeRefKind::BigInt(literal),
+        })
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<BencodeRef<'a>>, BencodeError> {
+        self.enter_container()?;
+        self.expect_byte(b'l', "'l'")?;
+        let mut items = Vec::new();
+
+        loop {
+            match self.peek() {
+                Some(b'e') => {
+                    self.index += 1; // consume list terminator
+                    break;
+                }
+                Some(_) => {
+                    self.count_item()?;
+                    items.push(self.parse_ref()?);
+                }
+                None => return Err(self.unexpected_eof("a list item or 'e'")),
+            }
+        }
+
+        self.depth -= 1;
+        Ok(items)
+    }
+
+    fn parse_dictionary(&mut self) -> Result<Vec<(&'a [u8], BencodeRef<'a>)>, BencodeError> {
+        self.enter_container()?;
+        self.expect_byte(b'd', "'d'")?;
+        let mut entries = Vec::new();
+        let mut previous_key: Option<&[u8]> = None;
+
+        loop {
+            match self.peek() {
+                Some(b'e') => {
+                    self.index += 1; // consume dictionary terminator
+                    break;
+                }
+                Some(c) if c.is_ascii_digit() => {
+                    let key_start = self.index;
+                    self.count_item()?;
+                    let key = self.parse_string()?;
+                    if self.strict {
+                        if let Some(previous) = previous_key {
+                            if key == previous {
+                                return Err(self.non_canonical(key_start, "no duplicate keys"));
+                            }
+                            if key < previous {
+                                return Err(
+                                    self.non_canonical(key_start, "keys sorted by raw bytes")
+                                );
+                            }
+                        }
+                        previous_key = Some(key);
+                    }
+                    let value = self.parse_ref()?;
+                    entries.push((key, value));
+                }
+                Some(_) => return Err(self.unexpected_byte("a byte string dictionary key or 'e'")),
+                None => return Err(self.unexpected_eof("a dictionary key or 'e'")),
+            }
+        }
+
+        self.depth -= 1;
+        Ok(entries)
+    }
+
+    fn enter_container(&mut self) -> Result<(), BencodeError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(self.limit_exceeded(self.index, "nesting depth", self.limits.max_depth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn count_item(&mut self) -> Result<(), BencodeError> {
+        if self.items >= self.limits.max_items {
+            return Err(self.limit_exceeded(self.index, "item count", self.limits.max_items));
+        }
+        self.items += 1;
+        Ok(())
+    }
+
+    pub fn ensure_consumed(&self) -> Result<(), BencodeError> {
+        if self.index != self.input.len() {
+            return Err(BencodeError::TrailingData {
+                offset: self.index,
+                context: context_snippet(self.input, self.index),
+            });
+        }
+        Ok(())
+    }
+
+    /// Current byte offset into the input.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.index).copied()
+    }
+
+    /// Advance past bytes matching `predicate`, returning how many were consumed.
+    fn skip_while(&mut self, predicate: impl Fn(u8) -> bool) -> usize {
+        let start = self.index;
+        while self.peek().is_some_and(&predicate) {
+            self.index += 1;
+        }
+        self.index - start
+    }
+
+    fn expect_byte(&mut self, expected: u8, description: &'static str) -> Result<(), BencodeError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.index += 1;
+                Ok(())
+            }
+            Some(_) => Err(self.unexpected_byte(description)),
+            None => Err(self.unexpected_eof(description)),
+        }
+    }
+
+    fn unexpected_byte(&self, expected: &'static str) -> BencodeError {
+        let found = self
+            .peek()
+            .map(|b| [b].escape_ascii().to_string())
+            .unwrap_or_default();
+        BencodeError::UnexpectedByte {
+            offset: self.index,
+            expected,
+            found,
+            context: context_snippet(self.input, self.index),
+        }
+    }
+
+    fn limit_exceeded(&self, offset: usize, limit: &'static str, max: usize) -> BencodeError {
+        BencodeError::LimitExceeded {
+            offset,
+            limit,
+            max,
+            context: context_snippet(self.input, offset),
+        }
+    }
+
+    fn non_canonical(&self, offset: usize, expected: &'static str) -> BencodeError {
+        BencodeError::NonCanonical {
+            offset,
+            expected,
+            context: context_snippet(self.input, offset),
+        }
+    }
+
+    fn unexpected_eof(&self, expected: &'static str) -> BencodeError {
+        BencodeError::UnexpectedEof {
+            offset: self.index,
+            expected,
+            context: context_snippet(self.input, self.index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::{parse_bytes, parse_string};
+
+    fn dict(entries: &[(&str, BencodeValue)]) -> BencodeValue {
+        entries.iter().cloned().collect()
+    }
+
+    #[test]
+    fn decode_parses_simple_string() {
+        assert_eq!(
+            parse_string("5:hello").unwrap(),
+            BencodeValue::from("hello")
+        );
+    }
+
+    #[test]
+    fn decode_parses_negative_integer() {
+        assert_eq!(parse_string("i-42e").unwrap(), BencodeValue::Int(-42));
+    }
+
+    #[test]
+    fn decode_parses_mixed_list() {
+        assert_eq!(
+            parse_string("l5:helloi42ee").unwrap(),
+            BencodeValue::List(vec!["hello".into(), 42.into()])
+        );
+    }
+
+    #[test]
+    fn decode_parses_dictionary_with_multiple_value_types() {
+        assert_eq!(
+            parse_string("d3:bar4:spam3:fooi42ee").unwrap(),
+            dict(&[("bar", "spam".into()), ("foo", 42.into())])
+        );
+    }
+
+    #[test]
+    fn decode_parses_nested_structures() {
+        assert_eq!(
+            parse_string("d4:listl4:spam4:eggse4:nestd3:key5:valueee").unwrap(),
+            dict(&[
+                (
+                    "list",
+                    BencodeValue::List(vec!["spam".into(), "eggs".into()])
+                ),
+                ("nest", dict(&[("key", "value".into())])),
+            ])
+        );
+    }
+
+    #[test]
+    fn decode_keeps_binary_strings_as_bytes() {
+        let value = parse_bytes(b"4:\xFF\x00\xC3\x28".to_vec()).unwrap();
+        assert_eq!(value, BencodeValue::Bytes(vec![0xFF, 0x00, 0xC3, 0x28]));
+        assert_eq!(value.encode(), b"4:\xFF\x00\xC3\x28");
+    }
+
+    #[test]
+    fn decode_dictionary_requires_string_keys() {
+        let mut parser = BencodeParser::new(b"di1ei1ee");
+        let err = parser.parse_value().unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::UnexpectedByte { offset: 1, ref found, .. } if found == "i"
+        ));
+    }
+
+    #[test]
+    fn decode_ensure_consumed_detects_trailing_data() {
+        let mut parser = BencodeParser::new(b"5:helloi1e");
+        parser.parse_value().unwrap();
+        assert_eq!(
+            parser.ensure_consumed().unwrap_err(),
+            BencodeError::TrailingData {
+                offset: 7,
+                context: "5:hello>>i1e".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn decode_reports_truncated_input() {
+        let err = parse_string("l5:helloi42e").unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::UnexpectedEof { offset: 12, .. }
+        ));
+
+        let err = parse_string("10:short").unwrap_err();
+        assert!(matches!(err, BencodeError::UnexpectedEof { offset: 8, .. }));
+    }
+
+    #[test]
+    fn decode_reports_malformed_integers() {
+        assert!(matches!(
+            parse_string("i12x4e").unwrap_err(),
+            BencodeError::UnexpectedByte { offset: 3, .. }
+        ));
+        assert!(matches!(
+            parse_string("ie").unwrap_err(),
+            BencodeError::UnexpectedByte { offset: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn decode_keeps_out_of_range_integers_verbatim() {
+        for input in [
+            "i99999999999999999999e",
+            "i-9223372036854775809e",
+            "i000099999999999999999999e",
+            "d4:sizei340282366920938463463374607431768211456ee",
+        ] {
+            let value = parse_string(input).unwrap();
+            assert_eq!(value.encode(), input.as_bytes());
+        }
+        assert_eq!(
+            parse_string("i9223372036854775808e").unwrap(),
+            BencodeValue::BigInt("9223372036854775808".into())
+        );
+        assert_eq!(
+            parse_string("i-9223372036854775808e").unwrap(),
+            BencodeValue::Int(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn decode_keeps_non_canonical_integers_verbatim() {
+        for input in ["i0042e", "i-0e", "i-007e", "i00e", "li01ei-0ee"] {
+            let value = parse_string(input).unwrap();
+            assert_eq!(value.encode(), input.as_bytes());
+        }
+        let value = parse_string("i0042e").unwrap();
+        assert_eq!(value, BencodeValue::BigInt("0042".into()));
+        assert_eq!((value.as_int(), value.as_u64()), (Some(42), Some(42)));
+        let value = parse_string("i-0e").unwrap();
+        assert_eq!((value.as_int(), value.as_u64()), (Some(0), Some(0)));
+        assert_eq!(parse_string("i42e").unwrap(), BencodeValue::Int(42));
+    }
+
+    fn parse_strict(input: &str) -> Result<BencodeValue, BencodeError> {
+        crate::bencode::parse_bytes_strict(input.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn strict_accepts_canonical_input() {
+        let input = "d3:bari0e3:fooli-5e0:ee";
+        assert_eq!(parse_strict(input).unwrap(), parse_string(input).unwrap());
+    }
+
+    #[test]
+    fn strict_rejects_unsorted_and_duplicate_keys() {
+        let unsorted = "d3:fooi1e3:bari2ee";
+        assert!(parse_string(unsorted).is_ok());
+        let err = parse_strict(unsorted).unwrap_err();
+        assert!(matches!(err, BencodeError::NonCanonical { offset: 9, .. }));
+
+        let duplicate = "d3:fooi1e3:fooi2ee";
+        assert_eq!(parse_string(duplicate).unwrap(), dict(&[("foo", 2.into())]));
+        let err = parse_strict(duplicate).unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::NonCanonical {
+                offset: 9,
+                expected: "no duplicate keys",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn strict_rejects_non_canonical_numbers() {
+        for (input, offset) in [("i03e", 1), ("i-03e", 1), ("i-0e", 1), ("l05:helloe", 1)] {
+            assert!(
+                parse_string(input).is_ok(),
+                "{input} should parse leniently"
+            );
+            let err = parse_strict(input).unwrap_err();
+            assert!(
+                matches!(err, BencodeError::NonCanonical { .. }) && err.offset() == Some(offset),
+                "{input}: {err}"
+            );
+        }
+        assert!(parse_strict("i0e").is_ok());
+        assert!(parse_strict("0:").is_ok());
+    }
+
+    fn parse_limited(input: &[u8], limits: DecodeLimits) -> Result<BencodeValue, BencodeError> {
+        crate::bencode::parse_bytes_limited(input.to_vec(), limits)
+    }
+
+    #[test]
+    fn limits_reject_deep_nesting() {
+        let input = [b"l".repeat(100_000), b"e".repeat(100_000)].concat();
+        let err = parse_limited(&input, DecodeLimits::untrusted()).unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::LimitExceeded {
+                offset: 64,
+                limit: "nesting depth",
+                max: 64,
+                ..
+            }
+        ));
+        let err = crate::bencode::parse_ref_limited(&input, DecodeLimits::untrusted()).unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::LimitExceeded { offset: 64, .. }
+        ));
+    }
+
+    #[test]
+    fn limits_reject_oversized_strings_before_reading_them() {
+        let limits = DecodeLimits {
+            max_string_len: 4,
+            ..DecodeLimits::unlimited()
+        };
+        assert!(parse_limited(b"4:abcd", limits).is_ok());
+        let err = parse_limited(b"l999999999999:e", limits).unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::LimitExceeded {
+                offset: 1,
+                limit: "string length",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn limits_reject_too_many_items_and_large_input() {
+        let limits = DecodeLimits {
+            max_items: 3,
+            max_total_size: 16,
+            ..DecodeLimits::unlimited()
+        };
+        assert!(parse_limited(b"d1:ai1e1:bli1eee", limits).is_ok());
+        let err = parse_limited(b"li1ei2ei3ei4ee", limits).unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::LimitExceeded {
+                offset: 10,
+                limit: "item count",
+                ..
+            }
+        ));
+        let err = parse_limited(&[b'0'; 17], limits).unwrap_err();
+        assert!(matches!(
+            err,
+            BencodeError::LimitExceeded {
+                offset: 0,
+                limit: "input size",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn decode_error_message_includes_position_and_context() {
+        let err = parse_string("d3:foo").unwrap_err();
+        assert_eq!(err.offset(), Some(6));
+        assert_eq!(
+            err.to_string(),
+            "unexpected end of input at byte 6: expected a value (near `d3:foo>>`)"
+        );
+    }
+}
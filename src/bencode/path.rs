@@ -0,0 +1,210 @@
+//! jq-like selectors over a parsed bencode tree, e.g. `info.files[3].path` or
+//! `announce-list[0][0]`.
+//!
+//! A path is a sequence of segments: `key` or `.key` selects a dictionary entry, `[n]`
+//! selects a list item and `["key"]` selects a dictionary entry whose key contains `.`, `[`
+//! or `"`. An empty path selects the root.
+
+use thiserror::Error;
+
+use super::{BencodeRef, BencodeRefKind};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl std::fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Key(key) if key.contains(['.', '[', '"']) => write!(f, "[{:?}]", key),
+            PathSegment::Key(key) => write!(f, ".{}", key),
+            PathSegment::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PathError {
+    #[error("invalid path at position {position}: {message}")]
+    Syntax { position: usize, message: String },
+    #[error("{at}: no key '{key}'")]
+    MissingKey { at: String, key: String },
+    #[error("{at}: index {index} out of range for list of {len} items")]
+    IndexOutOfRange {
+        at: String,
+        index: usize,
+        len: usize,
+    },
+    #[error("{at}: cannot select {segment} from {found}")]
+    TypeMismatch {
+        at: String,
+        segment: String,
+        found: &'static str,
+    },
+}
+
+/// Parse a selector into its segments.
+pub fn parse_path(path: &str) -> Result<Vec<PathSegment>, PathError> {
+    let bytes = path.as_bytes();
+    let mut segments = Vec::new();
+    let mut index = usize::from(path.starts_with('.'));
+    let mut at_start = true;
+    let syntax = |position: usize, message: &str| PathError::Syntax {
+        position,
+        message: message.to_string(),
+    };
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'[' if bytes.get(index + 1) == Some(&b'"') => {
+                let start = index + 2;
+                let len = path[start..]
+                    .find("\"]")
+                    .ok_or_else(|| syntax(index, "unterminated quoted key"))?;
+                segments.push(PathSegment::Key(path[start..start + len].to_string()));
+                index = start + len + 2;
+            }
+            b'[' => {
+                let start = index + 1;
+                let len = path[start..]
+                    .find(']')
+                    .ok_or_else(|| syntax(index, "unterminated index"))?;
+                let number = path[start..start + len]
+                    .parse()
+                    .map_err(|_| syntax(start, "expected a list index"))?;
+                segments.push(PathSegment::Index(number));
+                index = start + len + 1;
+            }
+            b'.' => {
+                let start = index + 1;
+                let len = key_len(&path[start..]);
+                if len == 0 {
+                    return Err(syntax(start, "expected a key after '.'"));
+                }
+                segments.push(PathSegment::Key(path[start..start + len].to_string()));
+                index = start + len;
+            }
+            _ if at_start => {
+                let len = key_len(&path[index..]);
+                segments.push(PathSegment::Key(path[index..index + len].to_string()));
+                index += len;
+            }
+            _ => return Err(syntax(index, "expected '.' or '['")),
+        }
+        at_start = false;
+    }
+
+    Ok(segments)
+}
+
+fn key_len(rest: &str) -> usize {
+    rest.find(['.', '[']).unwrap_or(rest.len())
+}
+
+/// Select the sub-value of `root` addressed by `path`.
+pub fn select<'r, 'a>(
+    root: &'r BencodeRef<'a>,
+    path: &str,
+) -> Result<&'r BencodeRef<'a>, PathError> {
+    let mut current = root;
+    let mut at = String::from("$");
+
+    for segment in parse_path(path)? {
+        current = match (&current.kind, &segment) {
+            (BencodeRefKind::Dict(_), PathSegment::Key(key)) => {
+                current.get(key).ok_or_else(|| PathError::MissingKey {
+                    at: at.clone(),
+                    key: key.clone(),
+                })?
+            }
+            (BencodeRefKind::List(items), PathSegment::Index(index)) => {
+                items.get(*index).ok_or(PathError::IndexOutOfRange {
+                    at: at.clone(),
+                    index: *index,
+                    len: items.len(),
+                })?
+            }
+            _ => {
+                return Err(PathError::TypeMismatch {
+                    at,
+                    segment: segment.to_string(),
+                    found: current.type_name(),
+                })
+            }
+        };
+        at.push_str(&segment.to_string());
+    }
+
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::parse_ref;
+
+    const TORRENT: &[u8] =
+        b"d8:announce3:url13:announce-listll1:aee4:infod5:filesld6:lengthi5e4:pathl1:x5:y.binee\
+e4:x.pei1eee";
+
+    #[test]
+    fn parses_keys_indexes_and_quoted_keys() {
+        use PathSegment::*;
+        assert_eq!(parse_path("").unwrap(), vec![]);
+        assert_eq!(parse_path(".").unwrap(), vec![]);
+        assert_eq!(
+            parse_path("info.files[3].path").unwrap(),
+            vec![
+                Key("info".into()),
+                Key("files".into()),
+                Index(3),
+                Key("path".into())
+            ]
+        );
+        assert_eq!(
+            parse_path(".announce-list[0][1]").unwrap(),
+            vec![Key("announce-list".into()), Index(0), Index(1)]
+        );
+        assert_eq!(
+            parse_path("info[\"x.pe\"]").unwrap(),
+            vec![Key("info".into()), Key("x.pe".into())]
+        );
+        assert!(parse_path("info.").is_err());
+        assert!(parse_path("files[x]").is_err());
+        assert!(parse_path("files[0").is_err());
+    }
+
+    #[test]
+    fn selects_nested_values() {
+        let root = parse_ref(TORRENT).unwrap();
+        assert_eq!(
+            select(&root, "announce-list[0][0]").unwrap().as_bytes(),
+            Some(&b"a"[..])
+        );
+        assert_eq!(
+            select(&root, "info.files[0].path[1]").unwrap().as_bytes(),
+            Some(&b"y.bin"[..])
+        );
+        assert_eq!(select(&root, "info[\"x.pe\"]").unwrap().as_int(), Some(1));
+        assert_eq!(select(&root, "").unwrap().raw(), TORRENT);
+    }
+
+    #[test]
+    fn reports_where_selection_failed() {
+        let root = parse_ref(TORRENT).unwrap();
+        assert_eq!(
+            select(&root, "info.files[2]").unwrap_err().to_string(),
+            "$.info.files: index 2 out of range for list of 1 items"
+        );
+        assert_eq!(
+            select(&root, "info.name").unwrap_err().to_string(),
+            "$.info: no key 'name'"
+        );
+        assert_eq!(
+            select(&root, "announce[0]").unwrap_err().to_string(),
+            "$.announce: cannot select [0] from byte string"
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+
+use serde::ser::{self, Error as _, Serialize};
+
+use super::{BencodeError, BencodeValue};
+
+/// Serialize `value` into canonical bencode.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, BencodeError> {
+    Ok(to_value(value)?.encode())
+}
+
+/// Serialize `value` into a [`BencodeValue`].
+///
+/// `None` and unit values have no bencode representation: they are skipped when they appear
+/// as a dictionary or struct field and rejected anywhere else.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<BencodeValue, BencodeError> {
+    value
+        .serialize(ValueSerializer)?
+        .ok_or_else(|| BencodeError::custom("top-level value has no bencode representation"))
+}
+
+/// Serializer producing `Some(value)`, or `None` for values that should be omitted.
+struct ValueSerializer;
+
+type SerResult = Result<Option<BencodeValue>, BencodeError>;
+
+fn int<T: TryInto<i64> + ToString + Copy>(n: T) -> SerResult {
+    Ok(Some(match n.try_into() {
+        Ok(n) => BencodeValue::Int(n),
+        Err(_) => BencodeValue::BigInt(n.to_string()),
+    }))
+}
+
+fn single_entry(variant: &str, value: BencodeValue) -> BencodeValue {
+    BencodeValue::Dict(BTreeMap::from([(variant.as_bytes().to_vec(), value)]))
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> SerResult {
+        Ok(Some(BencodeValue::from(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> SerResult {
+        int(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> SerResult {
+        Err(BencodeError::custom("bencode has no floating point type"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> SerResult {
+        Err(BencodeError::custom("bencode has no floating point type"))
+    }
+
+    fn serialize_char(self, v: char) -> SerResult {
+        Ok(Some(BencodeValue::from(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> SerResult {
+        Ok(Some(BencodeValue::from(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerResult {
+        Ok(Some(BencodeValue::from(v)))
+    }
+
+    fn serialize_none(self) -> SerResult {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> SerResult {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerResult {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> SerResult {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> SerResult {
+        Ok(Some(BencodeValue::from(variant)))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> SerResult {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> SerResult {
+        Ok(value
+            .serialize(self)?
+            .map(|value| single_entry(variant, value)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, BencodeError> {
+        Ok(SeqSerializer {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, BencodeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, BencodeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, BencodeError> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, BencodeError> {
+        Ok(MapSerializer {
+            variant: None,
+            entries: BTreeMap::new(),
+            pending_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, BencodeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, BencodeError> {
+        Ok(MapSerializer {
+            variant: Some(variant),
+            entries: BTreeMap::new(),
+            pending_key: None,
+        })
+    }
+}
+
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    items: Vec<BencodeValue>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodeError> {
+        let value = value
+            .serialize(ValueSerializer)?
+            .ok_or_else(|| BencodeError::custom("list items cannot be None or unit"))?;
+        self.items.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> SerResult {
+        let list = BencodeValue::List(self.items);
+        Ok(Some(match self.variant {
+            Some(variant) => single_entry(variant, list),
+            None => list,
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> SerResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> SerResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> SerResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> SerResult {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    variant: Option<&'static str>,
+    entries: BTreeMap<Vec<u8>, BencodeValue>,
+    pending_key: Option<Vec<u8>>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        key: Vec<u8>,
+        value: &T,
+    ) -> Result<(), BencodeError> {
+        if let Some(value) = value.serialize(ValueSerializer)? {
+            self.entries.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> SerResult {
+        let dict = BencodeValue::Dict(self.entries);
+        Ok(Some(match self.variant {
+            Some(variant) => single_entry(variant, dict),
+            None => dict,
+        }))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), BencodeError> {
+        match key.serialize(ValueSerializer)? {
+            Some(BencodeValue::Bytes(bytes)) => {
+                self.pending_key = Some(bytes);
+                Ok(())
+            }
+            _ => Err(BencodeError::custom(
+                "dictionary keys must be strings or bytes",
+            )),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), BencodeError> {
+        let key = self
+            .pending_key
+            .take()
+            .ok_or_else(|| BencodeError::custom("map value serialized before its key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> SerResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), BencodeError> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> SerResult {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Option<BencodeValue>;
+    type Error = BencodeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), BencodeError> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> SerResult {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Request {
+        msg_type: u64,
+        piece: u64,
+        #[serde(with = "serde_bytes")]
+        hash: Vec<u8>,
+        total_size: Option<u64>,
+    }
+
+    #[test]
+    fn serializes_structs_with_sorted_keys_and_skips_none() {
+        let encoded = to_bytes(&Request {
+            piece: 3,
+            msg_type: 0,
+            hash: vec![0xFF, 0x01],
+            total_size: None,
+        })
+        .unwrap();
+        assert_eq!(encoded, b"d4:hash2:\xFF\x018:msg_typei0e5:piecei3ee");
+    }
+
+    #[test]
+    fn serializes_maps_lists_and_enums() {
+        #[derive(Serialize)]
+        enum Event {
+            Started,
+            Stopped { at: u32 },
+        }
+
+        let map = BTreeMap::from([("m", vec![1u8, 2])]);
+        assert_eq!(to_bytes(&map).unwrap(), b"d1:mli1ei2eee");
+        assert_eq!(to_bytes(&Event::Started).unwrap(), b"7:Started");
+        assert_eq!(
+            to_bytes(&Event::Stopped { at: 5 }).unwrap(),
+            b"d7:Stoppedd2:ati5eee"
+        );
+    }
+
+    #[test]
+    fn serializes_integers_beyond_i64() {
+        assert_eq!(to_bytes(&u64::MAX).unwrap(), b"i18446744073709551615e");
+        assert_eq!(
+            to_bytes(&i128::MIN).unwrap(),
+            b"i-170141183460469231731687303715884105728e"
+        );
+    }
+
+    #[test]
+    fn rejects_values_without_bencode_representation() {
+        assert!(to_bytes(&1.5f64).is_err());
+        assert!(to_bytes(&vec![None::<u8>]).is_err());
+        assert!(to_bytes(&BTreeMap::from([(1, 2)])).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+
+/// A decoded bencode value.
+///
+/// Byte strings are kept as raw bytes; use [`BencodeValue::as_str`] when a UTF-8 view is
+/// needed. Dictionary keys are ordered by their raw bytes, which is the order bencode
+/// requires, so encoding a value always produces canonical output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BencodeValue {
+    Int(i64),
+    /// An integer `Int` cannot reproduce byte for byte: outside the `i64` range, or written
+    /// non-canonically (leading zeros, `-0`). Kept as its original literal (digits with an
+    /// optional leading `-`). Build it with [`BencodeValue::from_digits`], which only produces
+    /// this variant when `Int` would lose the literal.
+    BigInt(String),
+    Bytes(Vec<u8>),
+    List(Vec<BencodeValue>),
+    Dict(BTreeMap<Vec<u8>, BencodeValue>),
+}
+
+impl BencodeValue {
+    /// Short name of the value kind, used in error messages and CLI output.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            BencodeValue::Int(_) | BencodeValue::BigInt(_) => "integer",
+            BencodeValue::Bytes(_) => "byte string",
+            BencodeValue::List(_) => "list",
+            BencodeValue::Dict(_) => "dictionary",
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            BencodeValue::Int(n) => Some(*n),
+            BencodeValue::BigInt(digits) => digits.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Integer value, if it is non-negative.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            BencodeValue::BigInt(digits) => digits
+                .parse()
+                .ok()
+                .or_else(|| self.as_int().and_then(|n| u64::try_from(n).ok())),
+            _ => self.as_int().and_then(|n| u64::try_from(n).ok()),
+        }
+    }
+
+    /// Literal of an integer kept verbatim (see [`BencodeValue::BigInt`]).
+    pub fn as_big_int(&self) -> Option<&str> {
+        match self {
+            BencodeValue::BigInt(digits) => Some(digits),
+            _ => None,
+        }
+    }
+
+    /// Build an integer from its literal (digits with an optional leading `-`), falling back
+    /// to [`BencodeValue::BigInt`] when `Int` would not encode back to the same literal.
+    /// Returns `None` if `literal` is not an integer.
+    pub fn from_digits(literal: &str) -> Option<Self> {
+        let digits = literal.strip_prefix('-').unwrap_or(literal);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(match literal.parse::<i64>() {
+            Ok(n) if n.to_string() == literal => BencodeValue::Int(n),
+            _ => BencodeValue::BigInt(literal.to_string()),
+        })
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            BencodeValue::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Byte string value, if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_bytes().and_then(|b| std::str::from_utf8(b).ok())
+    }
+
+    pub fn as_list(&self) -> Option<&[BencodeValue]> {
+        match self {
+            BencodeValue::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&BTreeMap<Vec<u8>, BencodeValue>> {
+        match self {
+            BencodeValue::Dict(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Look up a dictionary entry. Returns `None` for missing keys and non-dictionaries.
+    pub fn get<K: AsRef<[u8]> + ?Sized>(&self, key: &K) -> Option<&BencodeValue> {
+        self.as_dict().and_then(|map| map.get(key.as_ref()))
+    }
+
+    /// Encode this value into its canonical bencoded form.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            BencodeValue::Int(n) => {
+                out.push(b'i');
+                out.extend_from_slice(n.to_string().as_bytes());
+                out.push(b'e');
+            }
+            BencodeValue::BigInt(digits) => {
+                out.push(b'i');
+                out.extend_from_slice(digits.as_bytes());
+                out.push(b'e');
+            }
+            BencodeValue::Bytes(bytes) => encode_bytes(bytes, out),
+            BencodeValue::List(items) => {
+                out.push(b'l');
+                for item in items {
+                    item.encode_into(out);
+                }
+                out.push(b'e');
+            }
+            BencodeValue::Dict(map) => {
+                out.push(b'd');
+                for (key, value) in map {
+                    encode_bytes(key, out);
+                    value.encode_into(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+
+    /// Render the value as JSON, with binary strings as hex markers (see [`super::to_json`]).
+    pub fn to_json(&self) -> serde_json::Value {
+        super::to_json(self, super::BinaryEncoding::Hex)
+    }
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+impl From<i64> for BencodeValue {
+    fn from(n: i64) -> Self {
+        BencodeValue::Int(n)
+    }
+}
+
+impl From<bool> for BencodeValue {
+    fn from(b: bool) -> Self {
+        BencodeValue::Int(b as i64)
+    }
+}
+
+impl From<&str> for BencodeValue {
+    fn from(s: &str) -> Self {
+        BencodeValue::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for BencodeValue {
+    fn from(s: String) -> Self {
+        BencodeValue::Bytes(s.into_bytes())
+    }
+}
+
+impl From<&[u8]> for BencodeValue {
+    fn from(bytes: &[u8]) -> Self {
+        BencodeValue::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for BencodeValue {
+    fn from(bytes: Vec<u8>) -> Self {
+        BencodeValue::Bytes(bytes)
+    }
+}
+
+impl From<Vec<BencodeValue>> for BencodeValue {
+    fn from(items: Vec<BencodeValue>) -> Self {
+        BencodeValue::List(items)
+    }
+}
+
+impl From<BTreeMap<Vec<u8>, BencodeValue>> for BencodeValue {
+    fn from(map: BTreeMap<Vec<u8>, BencodeValue>) -> Self {
+        BencodeValue::Dict(map)
+    }
+}
+
+impl<K: Into<Vec<u8>>, V: Into<BencodeValue>> FromIterator<(K, V)> for BencodeValue {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        BencodeValue::Dict(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_int() {
+        assert_eq!(BencodeValue::Int(42).encode(), b"i42e");
+        assert_eq!(BencodeValue::Int(-7).encode(), b"i-7e");
+    }
+
+    #[test]
+    fn encodes_bool_as_int() {
+        assert_eq!(BencodeValue::from(true).encode(), b"i1e");
+        assert_eq!(BencodeValue::from(false).encode(), b"i0e");
+    }
+
+    #[test]
+    fn encodes_list() {
+        let value = BencodeValue::List(vec![1.into(), "two".into(), 3.into()]);
+        assert_eq!(value.encode(), b"li1e3:twoi3ee");
+    }
+
+    #[test]
+    fn encodes_dictionary_with_sorted_keys() {
+        let value: BencodeValue = [("name", BencodeValue::from("Alice")), ("age", 30.into())]
+            .into_iter()
+            .collect();
+        assert_eq!(value.encode(), b"d3:agei30e4:name5:Alicee");
+    }
+
+    #[test]
+    fn encodes_binary_string_using_byte_length() {
+        let original = "éü😊";
+        let value = BencodeValue::from(original);
+
+        let mut expected = format!("{}:", original.len()).into_bytes();
+        expected.extend_from_slice(original.as_bytes());
+        assert_eq!(value.encode(), expected);
+
+        let binary = BencodeValue::Bytes(vec![0xFF, 0x00, 0x80]);
+        assert_eq!(binary.encode(), b"3:\xFF\x00\x80");
+    }
+
+    #[test]
+    fn accessors_return_typed_views() {
+        let value: BencodeValue = [
+            ("peers", BencodeValue::Bytes(vec![0xC0, 0xA8])),
+            ("interval", BencodeValue::Int(1800)),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            value.get("interval").and_then(BencodeValue::as_u64),
+            Some(1800)
+        );
+        assert_eq!(
+            value.get("peers").and_then(BencodeValue::as_bytes),
+            Some(&[0xC0, 0xA8][..])
+        );
+        assert_eq!(value.get("peers").and_then(BencodeValue::as_str), None);
+        assert!(value.get("missing").is_none());
+        assert_eq!(BencodeValue::Int(-1).as_u64(), None);
+    }
+}
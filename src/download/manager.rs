@@ -1,10 +1,12 @@
-use std::fs::OpenOptions;
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
 use std::thread;
 
 use super::queue::PieceQueue;
+use super::storage::Storage;
 use super::worker::PeerWorker;
-use crate::peer::PeerSessionConfig;
+use crate::peer::{DiscoveryPolicy, PeerSessionConfig, PeerSource};
 use crate::torrent::TorrentMetainfo;
 use crate::tracker;
 use crate::{log_error, log_info};
@@ -13,62 +15,75 @@ use crate::{log_error, log_info};
 /// It manages peer connections, piece downloading, and file assembly.
 pub struct DownloadManager {
     metainfo: Arc<TorrentMetainfo>,
+    policy: DiscoveryPolicy,
     client_id: String,
     output_path: String,
+    /// Files to download (BEP-53 `so`), as positions in [`TorrentMetainfo::files`]; `None`
+    /// for all of them.
+    selected_files: Option<Vec<u32>>,
+    /// Peers known up front, e.g. a magnet link's `x.pe` peers, and where they came from.
+    known_peers: Vec<(PeerSource, tracker::Peer)>,
 }
 
 impl DownloadManager {
     pub fn new(metainfo: TorrentMetainfo, client_id: String, output_path: String) -> Self {
         Self {
+            policy: DiscoveryPolicy::for_torrent(&metainfo),
             metainfo: Arc::new(metainfo),
             client_id,
             output_path,
+            selected_files: None,
+            known_peers: Vec::new(),
         }
     }
 
+    /// Also connect to `peers`, learned from `source`, if the torrent's discovery policy
+    /// allows that source.
+    pub fn with_peers(mut self, source: PeerSource, peers: Vec<tracker::Peer>) -> Self {
+        self.known_peers
+            .extend(peers.into_iter().map(|peer| (source, peer)));
+        self
+    }
+
+    /// Download only the files at `indices`, and only the pieces overlapping them.
+    pub fn select_files(mut self, indices: Vec<u32>) -> Self {
+        self.selected_files = Some(indices);
+        self
+    }
+
     pub fn download(&self) -> anyhow::Result<()> {
-        // 1. Get peers
-        let tracker_request = tracker::TrackerRequest {
-            info_hash: self.metainfo.info_hash.clone(),
-            peer_id: self.client_id.clone(),
-            port: 6881,
-            uploaded: 0,
-            downloaded: 0,
-            left: self.metainfo.length,
-            compact: 1,
+        let piece_ids = match &self.selected_files {
+            Some(indices) => self.metainfo.pieces_for_files(indices)?,
+            None => (0..self.metainfo.get_piece_count() as u32).collect(),
         };
+        // Without piece layers every large v2 piece would fail verification forever.
+        self.metainfo.check_piece_layers(&piece_ids)?;
 
-        let tracker_response = tracker::announce(self.metainfo.announce.clone(), tracker_request)?;
-        let peers = tracker_response.peers;
+        // 1. Get peers
+        let peers = self.find_peers()?;
         log_info!("DownloadManager", "Found {} peers", peers.len());
 
-        let num_pieces = self.metainfo.get_piece_count() as u64;
+        let output_path = Path::new(&self.output_path);
+        let storage = match &self.selected_files {
+            Some(indices) => Storage::create_selected(&self.metainfo, output_path, indices)?,
+            None => Storage::create(&self.metainfo, output_path)?,
+        };
         log_info!(
             "DownloadManager",
             "Total pieces to download: {}",
-            num_pieces
+            piece_ids.len()
         );
 
-        let piece_ids = (0..num_pieces as u32).collect::<Vec<u32>>();
         let queue = Arc::new(PieceQueue::new(&piece_ids));
-
-        let output_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .read(true)
-            .truncate(true)
-            .open(&self.output_path)?;
-        output_file.set_len(self.metainfo.length)?;
-        let shared_file = Arc::new(Mutex::new(output_file));
+        let storage = Arc::new(storage);
 
         let mut handles = vec![];
 
-        for peer in peers {
-            let peer = peer.clone();
+        for (peer, info_hash) in peers {
             let metainfo = self.metainfo.clone();
             let queue = queue.clone();
             let client_id = self.client_id.clone();
-            let file = shared_file.clone();
+            let storage = storage.clone();
 
             let handle = thread::spawn(move || {
                 let mut worker = PeerWorker::new(
@@ -76,10 +91,11 @@ impl DownloadManager {
                     metainfo,
                     queue,
                     client_id,
-                    file,
+                    storage,
                     0,
                     PeerSessionConfig::default(),
-                );
+                )
+                .with_info_hash(info_hash);
                 if let Err(e) = worker.run() {
                     log_error!("DownloadManager", "Worker failed: {}", e);
                 }
@@ -96,4 +112,67 @@ impl DownloadManager {
 
         Ok(())
     }
+
+    /// Announce under every swarm hash of the torrent (both, for a hybrid torrent) and pair
+    /// each peer with the hash to handshake under. Fails only if every announce fails and
+    /// there are no other peers.
+    ///
+    /// Known peers are only used if their source is allowed by the torrent's discovery
+    /// policy, so a private torrent never gets (or leaks) peers outside its own trackers.
+    fn find_peers(&self) -> anyhow::Result<Vec<(tracker::Peer, Vec<u8>)>> {
+        let mut trackers = tracker::TrackerTiers::new(self.metainfo.tracker_tiers());
+        let mut seen = HashSet::new();
+        let mut peers = Vec::new();
+        let mut last_error = None;
+
+        let swarm_hash = &self.metainfo.swarm_hashes()[0];
+        for (source, peer) in &self.known_peers {
+            if !self.policy.allows(*source) {
+                log_info!(
+                    "DownloadManager",
+                    "Private torrent: ignoring {:?} peer {}",
+                    source,
+                    peer
+                );
+            } else if seen.insert((peer.ip, peer.port)) {
+                peers.push((peer.clone(), swarm_hash.clone()));
+            }
+        }
+
+        for info_hash in self.metainfo.swarm_hashes() {
+            let tracker_request = tracker::TrackerRequest {
+                info_hash: info_hash.clone(),
+                peer_id: self.client_id.clone(),
+                port: 6881,
+                uploaded: 0,
+                downloaded: 0,
+                left: self.metainfo.length,
+                compact: 1,
+            };
+
+            match trackers.announce(&tracker_request) {
+                Ok(response) => {
+                    for peer in response.peers {
+                        if seen.insert((peer.ip, peer.port)) {
+                            peers.push((peer, info_hash.clone()));
+                        }
+                    }
+                }
+                Err(e) => {
+                    log_error!(
+                        "DownloadManager",
+                        "Announce for {} failed: {}",
+                        hex::encode(&info_hash),
+                        e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if peers.is_empty() => Err(e),
+            _ => Ok(peers),
+        }
+    }
 }
@@ -1,3 +1,4 @@
 pub mod manager;
 pub mod queue;
+pub mod storage;
 pub mod worker;
@@ -0,0 +1,157 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::torrent::{TorrentFile, TorrentMetainfo};
+
+/// On-disk storage for torrent data.
+///
+/// The torrent is treated as one contiguous byte range; writes are split across the files
+/// that range maps onto, so a piece spanning a file boundary lands in both files.
+pub struct Storage {
+    files: Vec<StorageFile>,
+}
+
+struct StorageFile {
+    offset: u64,
+    length: u64,
+    handle: Mutex<File>,
+}
+
+impl Storage {
+    /// Create (or truncate) the files of `metainfo` under `output_path`.
+    ///
+    /// A single-file torrent is written to `output_path` itself. For a multi-file torrent
+    /// `output_path` is the directory that takes the place of the torrent's `name`, and each
+    /// file is created at its relative path inside it. Padding files are not created; their
+    /// bytes are dropped on write.
+    pub fn create(metainfo: &TorrentMetainfo, output_path: &Path) -> anyhow::Result<Self> {
+        Self::create_with(metainfo, output_path, |_| true)
+    }
+
+    /// Like [`create`](Self::create), but only the files at `selected` (positions in
+    /// [`TorrentMetainfo::files`]) are created. Writes to the others are dropped, so nothing
+    /// is allocated for them.
+    pub fn create_selected(
+        metainfo: &TorrentMetainfo,
+        output_path: &Path,
+        selected: &[u32],
+    ) -> anyhow::Result<Self> {
+        Self::create_with(metainfo, output_path, |index| {
+            selected.contains(&(index as u32))
+        })
+    }
+
+    fn create_with<F>(
+        metainfo: &TorrentMetainfo,
+        output_path: &Path,
+        wanted: F,
+    ) -> anyhow::Result<Self>
+    where
+        F: Fn(usize) -> bool,
+    {
+        if !metainfo.is_multi_file() {
+            return Self::single_file(output_path, metainfo.length);
+        }
+
+        let files = metainfo
+            .files()
+            .iter()
+            .enumerate()
+            .filter(|(index, file)| !file.padding && wanted(*index))
+            .map(|(_, file)| Self::open(&output_path.join(file.relative_path()), file))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { files })
+    }
+
+    /// Storage backed by a single file of `length` bytes.
+    pub fn single_file(path: &Path, length: u64) -> anyhow::Result<Self> {
+        let file = TorrentFile {
+            path: vec![],
+            length,
+            offset: 0,
+            pieces_root: None,
+            padding: false,
+            md5sum: None,
+        };
+        Ok(Self {
+            files: vec![Self::open(path, &file)?],
+        })
+    }
+
+    fn open(path: &Path, file: &TorrentFile) -> anyhow::Result<StorageFile> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let handle = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .read(true)
+            .truncate(true)
+            .open(path)?;
+        handle.set_len(file.length)?;
+
+        Ok(StorageFile {
+            offset: file.offset,
+            length: file.length,
+            handle: Mutex::new(handle),
+        })
+    }
+
+    /// Write `data` at byte `offset` of the torrent data.
+    pub fn write(&self, offset: u64, data: &[u8]) -> anyhow::Result<()> {
+        let end = offset + data.len() as u64;
+        for file in &self.files {
+            let file_end = file.offset + file.length;
+            if file_end <= offset || file.offset >= end {
+                continue;
+            }
+
+            let start = offset.max(file.offset);
+            let stop = end.min(file_end);
+            let chunk = &data[(start - offset) as usize..(stop - offset) as usize];
+
+            let mut handle = file.handle.lock().unwrap();
+            handle.seek(SeekFrom::Start(start - file.offset))?;
+            handle.write_all(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_writes_across_file_boundaries() {
+        let torrent = b"d8:announce3:url4:infod5:filesld6:lengthi3e4:pathl1:aeed6:lengthi0e4:pathl5:emptyeed6:lengthi5e4:pathl3:sub1:beee4:name3:dir12:piece lengthi4e6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = TorrentMetainfo::from_bytes(torrent).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let storage = Storage::create(&metainfo, dir.path()).unwrap();
+        storage.write(4, b"5678").unwrap();
+        storage.write(0, b"1234").unwrap();
+
+        assert_eq!(std::fs::read(dir.path().join("a")).unwrap(), b"123");
+        assert_eq!(std::fs::read(dir.path().join("empty")).unwrap(), b"");
+        assert_eq!(
+            std::fs::read(dir.path().join("sub").join("b")).unwrap(),
+            b"45678"
+        );
+    }
+
+    #[test]
+    fn creates_only_selected_files() {
+        let torrent = b"d8:announce3:url4:infod5:filesld6:lengthi3e4:pathl1:aeed6:lengthi5e4:pathl1:beee4:name3:dir12:piece lengthi4e6:pieces40:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = TorrentMetainfo::from_bytes(torrent).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let storage = Storage::create_selected(&metainfo, dir.path(), &[1]).unwrap();
+        storage.write(0, b"1234").unwrap();
+
+        assert!(!dir.path().join("a").exists());
+        assert_eq!(std::fs::read(dir.path().join("b")).unwrap(), b"4\0\0\0\0");
+    }
+}
@@ -1,26 +1,25 @@
 use std::cmp::min;
-use std::fs::File;
-use std::io::{Seek, SeekFrom, Write};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use anyhow::anyhow;
 
 use super::queue::PieceQueue;
+use super::storage::Storage;
+use crate::log_debug;
 use crate::peer::{
     PeerCommand, PeerConnection, PeerEvent, PeerSession, PeerSessionConfig, PeerSessionHandler,
     SessionControl,
 };
 use crate::torrent::TorrentMetainfo;
 use crate::tracker::Peer;
-use crate::log_debug;
-use crate::utils::hash;
 
 pub struct PeerWorker {
     peer: Peer,
     metainfo: Arc<TorrentMetainfo>,
+    info_hash: Vec<u8>,
     queue: Arc<PieceQueue>,
     client_id: String,
-    output_file: Arc<Mutex<File>>,
+    storage: Arc<Storage>,
     base_piece_index: u32,
     config: PeerSessionConfig,
     active_download: Option<DownloadState>,
@@ -39,26 +38,34 @@ impl PeerWorker {
         metainfo: Arc<TorrentMetainfo>,
         queue: Arc<PieceQueue>,
         client_id: String,
-        output_file: Arc<Mutex<File>>,
+        storage: Arc<Storage>,
         base_piece_index: u32,
         config: PeerSessionConfig,
     ) -> Self {
         Self {
             peer,
+            info_hash: metainfo.info_hash.clone(),
             metainfo,
             queue,
             client_id,
-            output_file,
+            storage,
             base_piece_index,
             config,
             active_download: None,
         }
     }
 
+    /// Handshake under `info_hash` instead of the torrent's primary info hash, e.g. the
+    /// truncated v2 hash for a peer found in the v2 swarm of a hybrid torrent.
+    pub fn with_info_hash(mut self, info_hash: Vec<u8>) -> Self {
+        self.info_hash = info_hash;
+        self
+    }
+
     pub fn run(&mut self) -> anyhow::Result<()> {
         let session = PeerSession::new(
             self.peer.clone(),
-            self.metainfo.info_hash.clone(),
+            self.info_hash.clone(),
             self.client_id.clone(),
             self.config.clone(),
         );
@@ -67,32 +74,9 @@ impl PeerWorker {
     }
 
     fn persist_piece(&self, piece_index: u32, data: &[u8]) -> anyhow::Result<()> {
-        let offset_index = piece_index
-            .checked_sub(self.base_piece_index)
-            .unwrap_or(0) as u64;
+        let offset_index = piece_index.saturating_sub(self.base_piece_index) as u64;
         let offset = offset_index * self.metainfo.piece_length;
-        let mut file = self.output_file.lock().unwrap();
-        file.seek(SeekFrom::Start(offset))?;
-        file.write_all(data)?;
-        Ok(())
-    }
-
-    fn get_piece_len(&self, piece_index: u32) -> u32 {
-        let total_length = self.metainfo.length;
-        let piece_len = self.metainfo.piece_length;
-        let num_pieces = (total_length + piece_len - 1) / piece_len;
-
-        let current_piece_len = if piece_index as u64 == num_pieces - 1 {
-            let rem = total_length % piece_len;
-            if rem == 0 {
-                piece_len
-            } else {
-                rem
-            }
-        } else {
-            piece_len
-        };
-        current_piece_len as u32
+        self.storage.write(offset, data)
     }
 
     fn start_next_piece(&mut self, conn: &PeerConnection) -> anyhow::Result<()> {
@@ -100,8 +84,8 @@ impl PeerWorker {
             return Ok(());
         }
 
-        while let Some(piece_index) = self.queue.pop() {
-            let piece_len = self.get_piece_len(piece_index);
+        if let Some(piece_index) = self.queue.pop() {
+            let piece_len = self.metainfo.piece_len(piece_index) as u32;
             self.log(&format!(
                 "Downloading piece {} ({} bytes)",
                 piece_index, piece_len
@@ -115,7 +99,6 @@ impl PeerWorker {
             });
 
             self.send_next_request(conn)?;
-            return Ok(());
         }
 
         Ok(())
@@ -166,11 +149,9 @@ impl PeerWorker {
                 .take()
                 .expect("active download should exist when finishing piece");
 
-            let expected = self.metainfo.get_piece_hash_bytes(finished.index as usize);
-            let actual = hash::sha1(&finished.buffer);
-            if expected != actual.as_slice() {
+            if let Err(err) = self.metainfo.verify_piece(finished.index, &finished.buffer) {
                 self.queue.push(finished.index);
-                return Err(anyhow!("hash mismatch for piece {}", finished.index));
+                return Err(err);
             }
 
             self.persist_piece(finished.index, &finished.buffer)?;
@@ -1,14 +1,20 @@
 use codecrafters_bittorrent::{
     bencode,
-    download::{manager::DownloadManager, queue::PieceQueue, worker::PeerWorker},
-    peer::{metadata::MetadataFetcher, HandshakeRequest, PeerConnection, PeerSessionConfig},
-    torrent::{MagnetLink, TorrentMetainfo},
+    download::{manager::DownloadManager, queue::PieceQueue, storage::Storage, worker::PeerWorker},
+    peer::{
+        metadata::{resolve_peer, MetadataFetcher},
+        HandshakeRequest, PeerConnection, PeerSessionConfig, PeerSource,
+    },
+    torrent::{
+        self, MagnetLink, MetaVersion, MetadataCache, TorrentBuilder, TorrentEdit, TorrentMetainfo,
+    },
     tracker::{self, Peer},
-    utils::{log, RawBytesExt},
+    utils::{self, log, RawBytesExt},
 };
 use std::env;
-use std::fs::OpenOptions;
-use std::sync::{Arc, Mutex};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
 
 const PEER_ID: &str = "-CT0001-123456789012";
 
@@ -17,15 +23,131 @@ fn main() {
     log::set_global_log_level(log::LogLevel::Debug);
     log::set_global_log_handler(Box::new(console_log_handler));
 
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // Magnet commands keep fetched metadata in $XDG_CACHE_HOME (or ~/.cache); --no-cache
+    // neither reads nor writes it.
+    let use_cache = !(args[1].starts_with("magnet") && take_flag(&mut args, "--no-cache"));
     let command = &args[1];
 
     if command == "decode" {
-        // decode <bencoded string>
-        decode_bencoded_string(&args[2]);
+        // decode [--strict] [--format json|pretty|hex] [--binary hex|base64] (<bencoded string> | -f <file>)
+        let mut args = args[2..].to_vec();
+        let strict = take_flag(&mut args, "--strict");
+        let format = take_option(&mut args, "--format").unwrap_or_else(|| "json".to_string());
+        let binary = match take_option(&mut args, "--binary").map(|b| b.parse()) {
+            Some(Ok(binary)) => binary,
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            None => Default::default(),
+        };
+        let input = match take_option(&mut args, "-f") {
+            Some(path) => std::fs::read(path).expect("Failed to read input file"),
+            None => args[0].as_bytes().to_vec(),
+        };
+        decode_bencoded_string(input, strict, &format, binary);
+    } else if command == "encode" {
+        // encode [-o <output file>] (<json> | -f <file>)
+        let mut args = args[2..].to_vec();
+        let output = take_option(&mut args, "-o");
+        let input = match take_option(&mut args, "-f") {
+            Some(path) => std::fs::read_to_string(path).expect("Failed to read input file"),
+            None => args[0].clone(),
+        };
+        encode_json(&input, output.as_deref());
+    } else if command == "bget" {
+        // bget [--keys | --raw] <bencoded file> <path>
+        let mut args = args[2..].to_vec();
+        let keys = take_flag(&mut args, "--keys");
+        let raw = take_flag(&mut args, "--raw");
+        let path = args.get(1).map(String::as_str).unwrap_or("");
+        query_bencoded_file(&args[0], path, keys, raw);
+    } else if command == "create" {
+        // create [-o <output file>] [--piece-length <bytes>] [-t <tracker>[,<tracker>...]]...
+        //        [--web-seed <url>]... [--comment <text>] [--created-by <text>] [--source <tag>]
+        //        [--private] <file or directory>
+        let mut args = args[2..].to_vec();
+        let output = take_option(&mut args, "-o");
+        let piece_length = take_option(&mut args, "--piece-length");
+        let tiers = take_all_options(&mut args, "-t");
+        let web_seeds = take_all_options(&mut args, "--web-seed");
+        let comment = take_option(&mut args, "--comment");
+        let created_by = take_option(&mut args, "--created-by")
+            .unwrap_or_else(|| format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")));
+        let source = take_option(&mut args, "--source");
+        let private = take_flag(&mut args, "--private");
+
+        let mut builder = TorrentBuilder::new(&args[0])
+            .created_by(created_by)
+            .private(private);
+        if let Some(piece_length) = piece_length {
+            builder = builder.piece_length(piece_length.parse().expect("Invalid piece length"));
+        }
+        for tier in tiers {
+            builder = builder.tier(tier.split(',').map(str::to_string).collect());
+        }
+        for web_seed in web_seeds {
+            builder = builder.web_seed(web_seed);
+        }
+        if let Some(comment) = comment {
+            builder = builder.comment(comment);
+        }
+        if let Some(source) = source {
+            builder = builder.source(source);
+        }
+        create_torrent(builder, &args[0], output);
+    } else if command == "edit" {
+        // edit [-o <output file>] [-t <tracker>[,<tracker>...]]... [--remove-tracker <url>]...
+        //      [--replace-trackers] [--web-seed <url>]... [--remove-web-seed <url>]...
+        //      [--replace-web-seeds] [--comment <text> | --no-comment]
+        //      [--created-by <text> | --no-created-by] <metainfo file>
+        let mut args = args[2..].to_vec();
+        let output = take_option(&mut args, "-o");
+        let mut edit = TorrentEdit::new();
+        if take_flag(&mut args, "--replace-trackers") {
+            edit = edit.replace_trackers();
+        }
+        for tier in take_all_options(&mut args, "-t") {
+            edit = edit.tier(tier.split(',').map(str::to_string).collect());
+        }
+        for url in take_all_options(&mut args, "--remove-tracker") {
+            edit = edit.remove_tracker(url);
+        }
+        if take_flag(&mut args, "--replace-web-seeds") {
+            edit = edit.replace_web_seeds();
+        }
+        for url in take_all_options(&mut args, "--web-seed") {
+            edit = edit.web_seed(url);
+        }
+        for url in take_all_options(&mut args, "--remove-web-seed") {
+            edit = edit.remove_web_seed(url);
+        }
+        if let Some(comment) = take_option(&mut args, "--comment") {
+            edit = edit.comment(Some(comment));
+        } else if take_flag(&mut args, "--no-comment") {
+            edit = edit.comment(None);
+        }
+        if let Some(created_by) = take_option(&mut args, "--created-by") {
+            edit = edit.created_by(Some(created_by));
+        } else if take_flag(&mut args, "--no-created-by") {
+            edit = edit.created_by(None);
+        }
+        let output = output.unwrap_or_else(|| args[0].clone());
+        edit_torrent(&edit, &args[0], &output);
+    } else if command == "magnet" {
+        // magnet [--peer <host:port>]... <metainfo file>
+        let mut args = args[2..].to_vec();
+        let peers = take_all_options(&mut args, "--peer");
+        print_magnet_link(&args[0], &peers);
+    } else if command == "lint" {
+        // lint <metainfo file>
+        lint_torrent(&args[2]);
     } else if command == "info" {
-        // info <metainfo file>
-        print_torrent_info(&args[2]);
+        // info [--verbose] <metainfo file>
+        let mut args = args[2..].to_vec();
+        let verbose = take_flag(&mut args, "--verbose");
+        print_torrent_info(&args[0], verbose);
     } else if command == "peers" {
         // peers <metainfo file>
         request_tracker_peers(&args[2]);
@@ -50,49 +172,314 @@ fn main() {
         // link sample: magnet:?xt=urn:btih:ad42ce8109f54c99613ce38f9b4d87e70f24a165&dn=magnet1.gif&tr=http%3A%2F%2Fbittorrent-test-tracker.codecrafters.io%2Fannounce
         magnet_handshake(&args[2]);
     } else if command == "magnet_info" {
-        // magnet_info <magnet link>
-        magnet_info(&args[2]);
+        // magnet_info [--no-cache] <magnet link>
+        magnet_info(&args[2], use_cache);
     } else if command == "magnet_download_piece" {
-        // magnet_download_piece -o <output file> <magnet link> <piece index>
+        // magnet_download_piece [--no-cache] -o <output file> <magnet link> <piece index>
         download_magnet_piece(
             &args[3],
             args[4].as_str(),
             args[5].parse().expect("Invalid piece index"),
+            use_cache,
         );
     } else if command == "magnet_download" {
-        // magnet_download -o <output file> <magnet link>
-        download_magnet_file(&args[3], &args[4]);
+        // magnet_download [--no-cache] -o <output file> <magnet link>
+        download_magnet_file(&args[3], &args[4], use_cache);
+    } else if command == "magnet_to_torrent" {
+        // magnet_to_torrent [--no-cache] <magnet link> -o <output file>
+        let mut args = args[2..].to_vec();
+        let output = take_option(&mut args, "-o").expect("Missing -o <output file>");
+        save_magnet_torrent(&args[0], &output, use_cache);
     } else {
         println!("unknown command: {}", args[1])
     }
 }
 
+/// Remove `flag` from `args`, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len_before = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len_before
+}
+
+/// Remove `flag` and the value following it from `args`, returning the value.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    let value = args.get(position + 1)?.clone();
+    args.drain(position..=position + 1);
+    Some(value)
+}
+
+/// Remove every occurrence of `flag` and its value from `args`, returning the values in order.
+fn take_all_options(args: &mut Vec<String>, flag: &str) -> Vec<String> {
+    std::iter::from_fn(|| take_option(args, flag)).collect()
+}
+
 /// task 1: Decode bencoded string
 /// task 2: Decode bencoded integers
 /// task 3: Decode bencoded lists
 /// task 4: Decode bencoded dictionaries
-fn decode_bencoded_string(encoded_value: &str) {
-    let decoded_value = bencode::parse_string(encoded_value);
-    println!("{}", decoded_value.to_string());
+fn decode_bencoded_string(
+    bytes: Vec<u8>,
+    strict: bool,
+    format: &str,
+    binary: bencode::BinaryEncoding,
+) {
+    if !matches!(format, "json" | "pretty" | "hex") {
+        eprintln!("Unknown format: {} (expected json, pretty or hex)", format);
+        std::process::exit(1);
+    }
+
+    let mut parser = bencode::BencodeParser::new(&bytes).strict(strict);
+    let rendered = parser.parse_ref().and_then(|value| {
+        parser.ensure_consumed()?;
+        Ok(match format {
+            "hex" => bencode::dump(&value),
+            "pretty" => {
+                serde_json::to_string_pretty(&bencode::ref_to_json(&value, binary)).unwrap()
+            }
+            _ => bencode::ref_to_json(&value, binary).to_string(),
+        })
+    });
+
+    match rendered {
+        Ok(output) => println!("{}", output.trim_end()),
+        Err(e) => {
+            eprintln!("Failed to decode bencoded value: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Encode JSON (as printed by `decode`) back into bencode, written to `output_file_path`
+/// or to stdout.
+fn encode_json(json: &str, output_file_path: Option<&str>) {
+    let json: serde_json::Value = serde_json::from_str(json).expect("Invalid JSON input");
+    let encoded = match bencode::encode_json(&json) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            eprintln!("Failed to encode value: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match output_file_path {
+        Some(path) => std::fs::write(path, encoded).expect("Failed to write output file"),
+        None => std::io::stdout()
+            .write_all(&encoded)
+            .expect("Failed to write to stdout"),
+    }
+}
+
+/// Print the value at `path` inside a bencoded file: as JSON by default, as the list of
+/// dictionary keys with `keys`, or as raw bytes with `raw` (the contents of a byte string,
+/// the original encoding of anything else).
+fn query_bencoded_file(file_path: &str, path: &str, keys: bool, raw: bool) {
+    let bytes = std::fs::read(file_path).expect("Failed to read input file");
+    let selected = bencode::parse_ref(&bytes)
+        .map_err(anyhow::Error::from)
+        .and_then(|root| {
+            let value = bencode::select(&root, path)?;
+            let mut output = Vec::new();
+            if keys {
+                let entries = value.as_dict().ok_or_else(|| {
+                    anyhow::anyhow!("{} is a {}, not a dictionary", path, value.type_name())
+                })?;
+                for (key, _) in entries {
+                    match std::str::from_utf8(key) {
+                        Ok(key) => writeln!(output, "{}", key)?,
+                        Err(_) => writeln!(output, "{}", hex::encode(key))?,
+                    }
+                }
+            } else if raw {
+                output.extend_from_slice(value.as_bytes().unwrap_or(value.raw()));
+            } else {
+                let json = bencode::ref_to_json(value, Default::default());
+                writeln!(output, "{}", serde_json::to_string_pretty(&json)?)?;
+            }
+            Ok(output)
+        });
+
+    match selected {
+        Ok(output) => std::io::stdout()
+            .write_all(&output)
+            .expect("Failed to write to stdout"),
+        Err(e) => {
+            eprintln!("bget failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Hash `input_path` into a .torrent file, written to `output_file_path` or
+/// `<input name>.torrent`.
+fn create_torrent(builder: TorrentBuilder, input_path: &str, output_file_path: Option<String>) {
+    let bytes = builder.build().expect("Failed to create torrent");
+    let metainfo = TorrentMetainfo::from_bytes(&bytes).expect("Created an invalid torrent");
+
+    let output_file_path = output_file_path.unwrap_or_else(|| {
+        let name = Path::new(input_path.trim_end_matches('/'))
+            .file_name()
+            .expect("Input path has no file name");
+        format!("{}.torrent", name.to_string_lossy())
+    });
+    std::fs::write(&output_file_path, bytes).expect("Failed to write torrent file");
+
+    println!("Created {}", output_file_path);
+    println!("Info Hash: {}", metainfo.get_info_hash_hex());
+    println!("Piece Length: {}", metainfo.piece_length);
+    println!("Pieces: {}", metainfo.get_piece_count());
+}
+
+fn edit_torrent(edit: &TorrentEdit, metainfo_file_path: &str, output_file_path: &str) {
+    let mut metainfo = TorrentMetainfo::parse(metainfo_file_path).expect("Invalid torrent file");
+    let old_hash = metainfo.get_info_hash_hex();
+
+    edit.apply(&mut metainfo);
+    let bytes = metainfo.to_bytes().expect("Failed to encode torrent");
+    let new_hash = TorrentMetainfo::from_bytes(&bytes)
+        .expect("Edited torrent is invalid")
+        .get_info_hash_hex();
+    std::fs::write(output_file_path, bytes).expect("Failed to write torrent file");
+
+    println!("Wrote {}", output_file_path);
+    println!("Old Info Hash: {}", old_hash);
+    println!("New Info Hash: {}", new_hash);
+}
+
+fn print_magnet_link(metainfo_file_path: &str, peers: &[String]) {
+    let metainfo = TorrentMetainfo::parse(metainfo_file_path).expect("Invalid torrent file");
+    let mut magnet_link =
+        MagnetLink::from_metainfo(&metainfo).expect("Failed to build magnet link");
+    for peer in peers {
+        magnet_link
+            .peers
+            .push(peer.parse().expect("Invalid peer address"));
+    }
+    println!("{}", magnet_link.to_uri());
+}
+
+fn lint_torrent(metainfo_file_path: &str) {
+    let bytes = std::fs::read(metainfo_file_path).expect("Failed to read torrent file");
+    let issues = torrent::lint::lint(&bytes);
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if issues.is_empty() {
+        println!("No problems found");
+    }
+    if issues
+        .iter()
+        .any(|issue| issue.severity == torrent::lint::Severity::Error)
+    {
+        std::process::exit(1);
+    }
 }
 
 /// task 5: Parse torrent file
 /// task 6: Calculate info hash
 /// task 7: Piece hashes
-fn print_torrent_info(metainfo_file_path: &str) {
+fn print_torrent_info(metainfo_file_path: &str, verbose: bool) {
     let info = TorrentMetainfo::parse(metainfo_file_path).unwrap();
 
     print_metainfo(&info);
+    if verbose {
+        print_metainfo_details(&info);
+    }
 }
 
 fn print_metainfo(metainfo: &TorrentMetainfo) {
     println!("Tracker URL: {}", metainfo.announce);
     println!("Length: {}", metainfo.length);
     println!("Info Hash: {}", metainfo.get_info_hash_hex());
+    if let Some(hash) = metainfo.info_hash_v2 {
+        println!("Info Hash (v2): {}", hex::encode(hash));
+    }
     println!("Piece Length: {}", metainfo.piece_length);
-    println!("Piece Hashes:");
-    for hash in metainfo.get_piece_hashes() {
-        println!("{}", hash);
+    if metainfo.version != MetaVersion::V2 {
+        println!("Piece Hashes:");
+        for hash in metainfo.get_piece_hashes() {
+            println!("{}", hash);
+        }
+    }
+    if metainfo.version != MetaVersion::V1 {
+        println!("Pieces Roots:");
+        for file in metainfo.files().iter().filter(|file| !file.padding) {
+            let root = file.pieces_root.map(hex::encode).unwrap_or_default();
+            println!("{} {}", root, file.path.join("/"));
+        }
+    }
+}
+
+/// Everything else the metainfo says: optional fields, and a tree of the files with their
+/// sizes and the pieces they span.
+fn print_metainfo_details(metainfo: &TorrentMetainfo) {
+    println!("Name: {}", metainfo.name);
+    if let Some(comment) = &metainfo.comment {
+        println!("Comment: {}", comment);
+    }
+    if let Some(created_by) = &metainfo.created_by {
+        println!("Created By: {}", created_by);
+    }
+    if let Some(date) = metainfo.creation_date {
+        println!(
+            "Creation Date: {} ({})",
+            utils::format_unix_time(date),
+            date
+        );
+    }
+    if let Some(encoding) = &metainfo.encoding {
+        println!("Encoding: {}", encoding);
+    }
+    if let Some(source) = &metainfo.source {
+        println!("Source: {}", source);
+    }
+    println!("Private: {}", if metainfo.private { "yes" } else { "no" });
+    for tier in metainfo.tracker_tiers() {
+        println!("Tracker Tier: {}", tier.join(", "));
+    }
+    for url in &metainfo.url_list {
+        println!("Web Seed: {}", url);
+    }
+    println!("Pieces: {}", metainfo.get_piece_count());
+
+    println!("Files:");
+    let depth = usize::from(metainfo.is_multi_file());
+    if metainfo.is_multi_file() {
+        println!("  {}/", metainfo.name);
+    }
+    let mut current_dir: &[String] = &[];
+    for file in metainfo.files().iter().filter(|file| !file.padding) {
+        let (name, dir) = file.path.split_last().expect("file paths are not empty");
+        let common = current_dir
+            .iter()
+            .zip(dir)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for (level, component) in dir.iter().enumerate().skip(common) {
+            println!("{}{}/", "  ".repeat(depth + level + 1), component);
+        }
+        current_dir = dir;
+
+        let pieces = metainfo.piece_range(file);
+        let pieces = match pieces.len() {
+            0 => "no pieces".to_string(),
+            1 => format!("piece {}", pieces.start),
+            _ => format!("pieces {}-{}", pieces.start, pieces.end - 1),
+        };
+        let md5sum = file
+            .md5sum
+            .as_ref()
+            .map(|md5| format!(", md5 {}", md5))
+            .unwrap_or_default();
+        println!(
+            "{}{} ({} bytes, {}{})",
+            "  ".repeat(depth + dir.len() + 1),
+            name,
+            file.length,
+            pieces,
+            md5sum
+        );
     }
 }
 
@@ -110,8 +497,9 @@ fn request_tracker_peers(metainfo_file_path: &str) {
         compact: 1,
     };
 
-    let tracker_response =
-        tracker::announce(info.announce, tracker_request).expect("Failed to get tracker response");
+    let tracker_response = tracker::TrackerTiers::new(info.tracker_tiers())
+        .announce(&tracker_request)
+        .expect("Failed to get tracker response");
 
     for peer in tracker_response.peers {
         println!("{}", peer);
@@ -124,7 +512,7 @@ fn peer_handshake(metainfo_file_path: &str, peer: Peer) {
     let request = HandshakeRequest::new(meta.info_hash.clone(), PEER_ID.to_raw_bytes());
     let connection =
         PeerConnection::new(peer.clone(), &request).expect("Failed to establish peer connection");
-    let peer_id_hex = hex::encode(&connection.peer_id.unwrap());
+    let peer_id_hex = hex::encode(connection.peer_id.unwrap());
     println!("Peer ID: {}", peer_id_hex);
 }
 
@@ -156,7 +544,8 @@ fn download_piece_with_metainfo(
             compact: 1,
         };
 
-        let tracker_response = tracker::announce(meta.announce.clone(), tracker_request)
+        let tracker_response = tracker::TrackerTiers::new(meta.tracker_tiers())
+            .announce(&tracker_request)
             .expect("Failed to get tracker response");
         tracker_response.peers
     };
@@ -165,22 +554,11 @@ fn download_piece_with_metainfo(
         // 3. Setup queue seeded with the desired piece
         let queue = Arc::new(PieceQueue::new(&vec![piece_index]));
 
-        let offset = piece_index as u64 * meta.piece_length;
-        let piece_len = meta
-            .length
-            .saturating_sub(offset)
-            .min(meta.piece_length);
-        let output_file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .read(true)
-            .truncate(true)
-            .open(output_file_path)
-            .expect("Failed to create output file");
-        output_file
-            .set_len(piece_len)
-            .expect("Failed to size output file");
-        let shared_file = Arc::new(Mutex::new(output_file));
+        let piece_len = meta.piece_len(piece_index);
+        let storage = Arc::new(
+            Storage::single_file(Path::new(output_file_path), piece_len)
+                .expect("Failed to create output file"),
+        );
 
         // 4. Start worker
         let mut worker = PeerWorker::new(
@@ -188,7 +566,7 @@ fn download_piece_with_metainfo(
             meta.clone(),
             queue.clone(),
             PEER_ID.to_string(),
-            shared_file,
+            storage,
             piece_index,
             PeerSessionConfig::aggressive(),
         );
@@ -212,32 +590,40 @@ fn download_file(output_file_path: &str, metainfo_file_path: &str) {
 fn parse_magnet_link(link: &str) {
     let magnet_link = MagnetLink::parse(link).expect("Failed to parse magnet link");
 
-    // For now assume there is only one tracker
+    // Print the first tracker; the others are used as fallbacks when announcing
     let tracker_url = magnet_link
         .trackers
-        .iter()
-        .next()
+        .first()
         .map(|url| url.to_string())
         .expect("No trackers found");
 
-    // And only one info hash
-    let info_hash_hex = magnet_link
-        .exact_topics
-        .iter()
-        .next()
-        .map(|topic| topic.get_hash().expect("Unsupported scheme").to_string())
-        .expect("No info hash found");
-
     println!("Tracker URL: {}", tracker_url);
-    println!("Info Hash: {}", info_hash_hex);
+    // A link may name several torrents, or one hybrid torrent by both of its hashes.
+    for hash in magnet_link.info_hashes().expect("Invalid info hash") {
+        if hash.len() == 20 {
+            println!("Info Hash: {}", hex::encode(hash));
+        } else {
+            println!("Info Hash (v2): {}", hex::encode(hash));
+        }
+    }
+}
+
+/// Metadata fetcher for `link`, using the on-disk metadata cache when `use_cache` is set and
+/// there is one.
+fn metadata_fetcher(link: &str, handshake_only: bool, use_cache: bool) -> MetadataFetcher {
+    let fetcher = MetadataFetcher::new(link, PEER_ID.to_string(), handshake_only)
+        .expect("Failed to create metadata fetcher");
+    match MetadataCache::default_location().filter(|_| use_cache) {
+        Some(cache) => fetcher.with_cache(cache),
+        None => fetcher,
+    }
 }
 
 /// magnet links | task 2: Announce extension support
 /// magnet links | task 3: Send extension handshake
 /// magnet links | task 4: Receive extension handshake
 fn magnet_handshake(link: &str) {
-    let mut metadata_fetcher = MetadataFetcher::new(link, PEER_ID.to_string(), true)
-        .expect("Failed to create metadata fetcher");
+    let mut metadata_fetcher = metadata_fetcher(link, true, false);
     let result = metadata_fetcher.run().expect("Metadata fetcher failed");
     if let Some(peer_id) = result.peer_id {
         println!("Peer ID: {}", hex::encode(peer_id));
@@ -249,9 +635,8 @@ fn magnet_handshake(link: &str) {
 
 /// magnet links | task 5: Request metadata
 /// magnet links | task 6: Receive metadata
-fn magnet_info(link: &str) {
-    let mut metadata_fetcher = MetadataFetcher::new(link, PEER_ID.to_string(), false)
-        .expect("Failed to create metadata fetcher");
+fn magnet_info(link: &str, use_cache: bool) {
+    let mut metadata_fetcher = metadata_fetcher(link, false, use_cache);
     let result = metadata_fetcher.run().expect("Metadata fetcher failed");
     if let Some(peer_id) = result.peer_id {
         println!("Peer ID: {}", hex::encode(peer_id));
@@ -265,15 +650,10 @@ fn magnet_info(link: &str) {
 }
 
 /// magnet links | task 7: Download a piece
-fn download_magnet_piece(output_file_path: &str, link: &str, piece_index: u32) {
-    let metainfo = {
-        let mut metadata_fetcher = MetadataFetcher::new(link, PEER_ID.to_string(), false)
-            .expect("Failed to create metadata fetcher");
-        let result = metadata_fetcher.run().expect("Metadata fetcher failed");
-        result
-            .metainfo
-            .expect("Failed to retrieve metadata from peer")
-    };
+fn download_magnet_piece(output_file_path: &str, link: &str, piece_index: u32, use_cache: bool) {
+    let metainfo = metadata_fetcher(link, false, use_cache)
+        .fetch_metainfo()
+        .expect("Metadata fetcher failed");
 
     print_metainfo(&metainfo);
 
@@ -282,19 +662,35 @@ fn download_magnet_piece(output_file_path: &str, link: &str, piece_index: u32) {
 }
 
 /// magnet links | task 8: Download the whole file
-fn download_magnet_file(output_file_path: &str, link: &str) -> () {
-    let metainfo = {
-        let mut metadata_fetcher = MetadataFetcher::new(link, PEER_ID.to_string(), false)
-            .expect("Failed to create metadata fetcher");
-        let result = metadata_fetcher.run().expect("Metadata fetcher failed");
-        result
-            .metainfo
-            .expect("Failed to retrieve metadata from peer")
+fn download_magnet_file(output_file_path: &str, link: &str, use_cache: bool) {
+    let (metainfo, magnet_link) = {
+        let mut metadata_fetcher = metadata_fetcher(link, false, use_cache);
+        let metainfo = metadata_fetcher
+            .fetch_metainfo()
+            .expect("Metadata fetcher failed");
+        (metainfo, metadata_fetcher.magnet_link().clone())
     };
 
     print_metainfo(&metainfo);
 
     let client_id = PEER_ID.to_string();
-    let manager = DownloadManager::new(metainfo, client_id, output_file_path.to_string());
+    let x_pe_peers = magnet_link.peers.iter().flat_map(resolve_peer).collect();
+    let mut manager = DownloadManager::new(metainfo, client_id, output_file_path.to_string())
+        .with_peers(PeerSource::Magnet, x_pe_peers);
+    // BEP-53: a magnet may point at some of the files of a larger torrent.
+    if let Some(indices) = magnet_link.select_only {
+        manager = manager.select_files(indices);
+    }
     manager.download().expect("Download failed");
 }
+
+fn save_magnet_torrent(link: &str, output_file_path: &str, use_cache: bool) {
+    let metainfo = metadata_fetcher(link, false, use_cache)
+        .fetch_metainfo()
+        .expect("Metadata fetcher failed");
+    let bytes = metainfo.to_bytes().expect("Failed to encode torrent");
+    std::fs::write(output_file_path, bytes).expect("Failed to write torrent file");
+
+    println!("Wrote {}", output_file_path);
+    println!("Info Hash: {}", metainfo.get_info_hash_hex());
+}
@@ -0,0 +1,65 @@
+use crate::torrent::TorrentMetainfo;
+
+/// Where a peer address was learned from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerSource {
+    /// An announce to one of the torrent's own trackers.
+    Tracker,
+    /// The mainline DHT (BEP-5).
+    Dht,
+    /// Peer exchange with connected peers (BEP-11).
+    Pex,
+    /// Local service discovery multicast (BEP-14).
+    Lsd,
+    /// An `x.pe` peer given in a magnet link.
+    Magnet,
+}
+
+/// Which peer sources a torrent may use.
+///
+/// A private torrent (BEP-27) gets peers only from its own trackers and must never be
+/// announced anywhere else, so every decentralised source is off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiscoveryPolicy {
+    private: bool,
+}
+
+impl DiscoveryPolicy {
+    /// Policy for a torrent whose `info.private` flag is `private`.
+    pub fn new(private: bool) -> Self {
+        Self { private }
+    }
+
+    pub fn for_torrent(metainfo: &TorrentMetainfo) -> Self {
+        Self::new(metainfo.private)
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// Whether peers from `source` may be used (and the torrent announced there).
+    pub fn allows(&self, source: PeerSource) -> bool {
+        !self.private || source == PeerSource::Tracker
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_torrents_only_use_trackers() {
+        let private = DiscoveryPolicy::new(true);
+        assert!(private.allows(PeerSource::Tracker));
+        for source in [
+            PeerSource::Dht,
+            PeerSource::Pex,
+            PeerSource::Lsd,
+            PeerSource::Magnet,
+        ] {
+            assert!(!private.allows(source), "{:?}", source);
+            assert!(DiscoveryPolicy::default().allows(source), "{:?}", source);
+        }
+    }
+}
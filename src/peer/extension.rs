@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize};
-use serde_bencode;
 use std::collections::BTreeMap;
 
+use crate::bencode;
+
 /// Placeholder for incoming/outgoing BEP-10 extension handshake payload.
 /// See: https://www.bittorrent.org/beps/bep_0010.html
 /// TODO: Implement encode/decode when enabling extensions.
@@ -42,11 +43,12 @@ impl ExtensionHandshakePayload {
             client_name: self.client_name.clone(),
         };
 
-        Ok(serde_bencode::to_bytes(&payload)?)
+        Ok(bencode::to_bytes(&payload)?)
     }
 
     pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
-        let payload: ExtensionHandshakeSerde = serde_bencode::from_bytes(bytes)?;
+        let payload: ExtensionHandshakeSerde =
+            bencode::from_bytes_limited(bytes, bencode::DecodeLimits::untrusted())?;
 
         let extensions = payload.extensions.into_iter().collect::<Vec<_>>();
 
@@ -70,7 +72,7 @@ impl ExtensionHandshakePayload {
 impl ExtensionMessage {
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(1 + self.payload.len());
-        buf.push(self.msg_id as u8);
+        buf.push(self.msg_id);
         buf.extend_from_slice(&self.payload);
         buf
     }
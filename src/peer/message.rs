@@ -70,7 +70,7 @@ impl HandshakeRequest {
 
         let mut buf = Vec::with_capacity(1 + pstr_bytes.len() + 8 + 20 + 20);
         buf.push(pstr_bytes.len() as u8);
-        buf.extend_from_slice(&pstr_bytes);
+        buf.extend_from_slice(pstr_bytes);
         buf.extend_from_slice(&self.reserved);
         buf.extend_from_slice(&self.info_hash);
         buf.extend_from_slice(&self.peer_id);
@@ -95,7 +95,7 @@ mod test {
     #[test]
     fn get_reserved_bytes() {
         let reserved = super::get_reserved_extension_support_bytes();
-        let hex_reserved = hex::encode(&reserved);
+        let hex_reserved = hex::encode(reserved);
         assert_eq!(hex_reserved, "0000000000100000");
     }
 }
@@ -1,21 +1,27 @@
-use anyhow::{bail, Context};
+use anyhow::{anyhow, bail, Context};
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use serde_bencode::de::Deserializer;
 use std::collections::HashSet;
-use std::io::Cursor;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
 use crate::{
+    bencode, log_debug, log_error,
     peer::{
         extension::ExtensionHandshakePayload, PeerCommand, PeerConnection, PeerEvent, PeerSession,
         PeerSessionConfig, PeerSessionHandler, SessionControl,
     },
-    torrent::{MagnetLink, TorrentMetainfo},
+    torrent::{MagnetLink, MetadataCache, PeerAddress, TorrentMetainfo},
     tracker,
-    log_debug, log_error,
 };
 
 const METADATA_EXTENSION_NAME: &str = "ut_metadata";
 const MY_METADATA_EXTENSION_MESSAGE_ID: u8 = 42;
+/// `left` to announce when the link has no `xl`. Any non-zero value will do: trackers take
+/// `left=0` to mean a seeder and may not send it other seeders.
+const UNKNOWN_LEFT: u64 = 16 * 1024;
 
 pub struct MetadataFetcher {
     magnet_link: MagnetLink,
@@ -30,6 +36,8 @@ pub struct MetadataFetcher {
     peer_metadata_id: Option<u8>,
     peer_id: Option<Vec<u8>>,
 
+    cache: Option<MetadataCache>,
+
     // For debugging purposes only
     handshake_only: bool,
 }
@@ -60,94 +68,223 @@ impl MetadataFetcher {
             handshake_only,
             peer_metadata_id: None,
             peer_id: None,
+            cache: None,
         })
     }
 
-    pub fn run(&mut self) -> anyhow::Result<MetadataFetchResult> {
-        // For now assume there is only one tracker
-        let tracker_url = self
-            .magnet_link
-            .trackers
-            .iter()
-            .next()
-            .map(|url| url.to_string())
-            .context("No trackers found")?;
-
-        // And only one info hash
-        let topic = self
-            .magnet_link
-            .exact_topics
-            .iter()
-            .next()
-            .context("No info hash found")?;
-
-        let info_hash = {
-            let hash = topic.get_hash().context("Unsupported scheme")?;
-            hex::decode(hash).context("Invalid info hash hex")?
-        };
-
-        // 2. Announce to tracker and get peers
-        let peers = {
-            let tracker_request = tracker::TrackerRequest {
-                info_hash: info_hash.clone(),
-                peer_id: self.client_id.clone(),
-                port: 6881,
-                uploaded: 0,
-                downloaded: 0,
-                left: 999,
-                compact: 1,
-            };
-
-            let tracker_response = tracker::announce(tracker_url.clone(), tracker_request)
-                .context("Failed to get tracker response")?;
-            tracker_response.peers
-        };
+    /// Look up and store fetched metadata in `cache`.
+    pub fn with_cache(mut self, cache: MetadataCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
 
-        for peer in &peers {
-            // 3. Run a lightweight session that sends extension handshake
-            let session = PeerSession::new(
-                peer.clone(),
-                info_hash.clone(),
-                self.client_id.clone(),
-                PeerSessionConfig::aggressive(),
-            );
+    pub fn magnet_link(&self) -> &MagnetLink {
+        &self.magnet_link
+    }
 
-            match session.run(self) {
-                Ok(_) => {
-                    let metainfo = match &self.metadata_bytes {
-                        Some(bytes) => {
-                            let metainfo =
-                                TorrentMetainfo::from_info_bytes(tracker_url.clone(), bytes)?;
+    /// Resolve the magnet link, from the metadata cache when one is set and has it.
+    pub fn run(&mut self) -> anyhow::Result<MetadataFetchResult> {
+        // A cached info dictionary has no peer behind it, so it is no use for a handshake.
+        if !self.handshake_only {
+            let cached = self
+                .cache
+                .as_ref()
+                .and_then(|cache| cache.load(&self.magnet_link));
+            if let Some(metainfo) = cached {
+                log_debug!(
+                    "MetadataFetcher",
+                    "Using cached metadata for {}",
+                    metainfo.get_info_hash_hex()
+                );
+                return Ok(MetadataFetchResult {
+                    peer_id: None,
+                    peer_metadata_id: None,
+                    metainfo: Some(metainfo),
+                });
+            }
+        }
 
-                            if metainfo.info_hash != info_hash {
-                                bail!("Downloaded metadata info hash does not match expected info hash");
-                            }
+        let result = self.resolve()?;
+        if let (Some(cache), Some(metainfo)) = (&self.cache, &result.metainfo) {
+            if let Err(e) = cache.store(metainfo) {
+                log_error!("MetadataFetcher", "Failed to cache metadata: {:#}", e);
+            }
+        }
+        Ok(result)
+    }
 
-                            log_debug!(
-                                "MetadataFetcher",
-                                "Info hash verified: {}",
-                                metainfo.get_info_hash_hex()
-                            );
+    /// Resolve the link to a complete torrent, ready to be saved with
+    /// [`TorrentMetainfo::to_bytes`].
+    pub fn fetch_metainfo(&mut self) -> anyhow::Result<TorrentMetainfo> {
+        self.run()?
+            .metainfo
+            .context("Failed to retrieve metadata from peer")
+    }
 
-                            Some(metainfo)
+    /// Fetch metadata using every source of the link at once: every tracker (under every
+    /// swarm hash), the `x.pe` peers and, unless only a handshake is wanted, the .torrent
+    /// files at its `xs` URLs. Peers are tried one at a time as they are found; whichever
+    /// source yields verified metadata first wins.
+    ///
+    /// Whether a torrent is private (BEP-27) is only known once its info dictionary is in
+    /// hand, so every source is used here. The [`DiscoveryPolicy`] applies from then on: the
+    /// download manager drops `x.pe` peers of a private torrent.
+    ///
+    /// [`DiscoveryPolicy`]: crate::peer::DiscoveryPolicy
+    fn resolve(&mut self) -> anyhow::Result<MetadataFetchResult> {
+        let swarm_hashes = self.magnet_link.swarm_hashes()?;
+        let found = self.spawn_sources(&swarm_hashes);
+
+        let mut tried = HashSet::new();
+        let mut last_error = None;
+        for found in found {
+            match found {
+                Found::Peers(peers) => {
+                    for (peer, info_hash) in peers {
+                        if !tried.insert((peer.ip, peer.port, info_hash.clone())) {
+                            continue;
                         }
-                        None => None,
-                    };
-
+                        match self.fetch_from_peer(&peer, &info_hash) {
+                            Ok(result) => return Ok(result),
+                            Err(e) => {
+                                log_error!("MetadataFetcher", "[{}] Session error: {:#}", peer, e);
+                                last_error = Some(e);
+                            }
+                        }
+                    }
+                }
+                Found::Torrent(metainfo) => {
                     return Ok(MetadataFetchResult {
-                        peer_id: self.peer_id.clone(),
-                        peer_metadata_id: self.peer_metadata_id,
-                        metainfo,
+                        peer_id: None,
+                        peer_metadata_id: None,
+                        metainfo: Some(*metainfo),
                     });
                 }
-                Err(e) => {
-                    log_error!("MetadataFetcher", "[{}] Session error: {:#}", peer, e);
-                    continue;
-                }
             }
         }
 
-        bail!("No peers responded with extension handshake");
+        Err(match last_error {
+            Some(e) => e.context("No peers responded with extension handshake"),
+            None => anyhow!("No peers found for magnet link"),
+        })
+    }
+
+    /// Start a thread per source, each sending what it finds down the returned channel. The
+    /// channel closes once every source has finished.
+    fn spawn_sources(&self, swarm_hashes: &[Vec<u8>]) -> Receiver<Found> {
+        let (sender, receiver) = mpsc::channel();
+
+        let peers = self.magnet_link.peers.clone();
+        let hashes = swarm_hashes.to_vec();
+        let tx = sender.clone();
+        thread::spawn(move || {
+            for address in &peers {
+                let found = resolve_peer(address)
+                    .into_iter()
+                    .flat_map(|peer| hashes.iter().map(move |hash| (peer.clone(), hash.clone())))
+                    .collect();
+                let _ = tx.send(Found::Peers(found));
+            }
+        });
+
+        for url in self.magnet_link.trackers.iter().map(Url::to_string) {
+            for info_hash in swarm_hashes {
+                let request = tracker::TrackerRequest {
+                    info_hash: info_hash.clone(),
+                    peer_id: self.client_id.clone(),
+                    port: 6881,
+                    uploaded: 0,
+                    downloaded: 0,
+                    left: self.magnet_link.length.unwrap_or(UNKNOWN_LEFT),
+                    compact: 1,
+                };
+                let url = url.clone();
+                let tx = sender.clone();
+                // Every source runs at once, so a silent tracker is not worth the spec's
+                // full retry schedule.
+                thread::spawn(move || {
+                    match tracker::announce_with_udp_timeouts(
+                        url.clone(),
+                        request.clone(),
+                        tracker::UdpTracker::BASE_TIMEOUT,
+                        tracker::UdpTracker::FALLBACK_RETRIES,
+                    ) {
+                        Ok(response) => {
+                            let found = response
+                                .peers
+                                .into_iter()
+                                .map(|peer| (peer, request.info_hash.clone()))
+                                .collect();
+                            let _ = tx.send(Found::Peers(found));
+                        }
+                        Err(e) => log_error!("MetadataFetcher", "Tracker {} failed: {:#}", url, e),
+                    }
+                });
+            }
+        }
+
+        // A .torrent file has no peer behind it, so it is no use for a handshake.
+        if !self.handshake_only {
+            for url in self.magnet_link.exact_sources.clone() {
+                let magnet_link = self.magnet_link.clone();
+                let tx = sender.clone();
+                thread::spawn(move || match fetch_exact_source(&magnet_link, &url) {
+                    Ok(metainfo) => {
+                        let _ = tx.send(Found::Torrent(Box::new(metainfo)));
+                    }
+                    Err(e) => log_error!("MetadataFetcher", "Source {} failed: {:#}", url, e),
+                });
+            }
+        }
+
+        receiver
+    }
+
+    /// Run a metadata session with one peer of the swarm of `info_hash`.
+    fn fetch_from_peer(
+        &mut self,
+        peer: &tracker::Peer,
+        info_hash: &[u8],
+    ) -> anyhow::Result<MetadataFetchResult> {
+        // A previous peer may have failed half way through; start over.
+        self.reset_session();
+
+        // 3. Run a lightweight session that sends extension handshake
+        let session = PeerSession::new(
+            peer.clone(),
+            info_hash.to_vec(),
+            self.client_id.clone(),
+            PeerSessionConfig::aggressive(),
+        );
+        session.run(self)?;
+
+        let metainfo = match &self.metadata_bytes {
+            Some(bytes) => {
+                let metainfo = self.magnet_link.to_metainfo(bytes)?;
+                if !metainfo.has_info_hash(info_hash) {
+                    bail!("Downloaded metadata info hash does not match expected info hash");
+                }
+                log_topics(&self.magnet_link, &metainfo)?;
+                Some(metainfo)
+            }
+            None => None,
+        };
+
+        Ok(MetadataFetchResult {
+            peer_id: self.peer_id.clone(),
+            peer_metadata_id: self.peer_metadata_id,
+            metainfo,
+        })
+    }
+
+    fn reset_session(&mut self) {
+        self.ext_handshake_sent = false;
+        self.metadata_bytes = None;
+        self.total_size = None;
+        self.requested_pieces.clear();
+        self.received_pieces.clear();
+        self.peer_metadata_id = None;
+        self.peer_id = None;
     }
 
     fn request_metadata_piece(&mut self, conn: &PeerConnection, piece: u64) -> anyhow::Result<()> {
@@ -155,7 +292,7 @@ impl MetadataFetcher {
             .peer_metadata_id
             .context("Missing metadata extension id from handshake")?;
 
-        let payload = serde_bencode::to_bytes(&PieceRequestPayloadSerde {
+        let payload = bencode::to_bytes(&PieceRequestPayloadSerde {
             msg_type: MetadataMessageType::Request as u64,
             piece,
         })?;
@@ -191,10 +328,9 @@ impl MetadataFetcher {
         conn: &PeerConnection,
         payload: &[u8],
     ) -> anyhow::Result<bool> {
-        let mut cursor = Cursor::new(payload);
-        let mut deserializer = Deserializer::new(&mut cursor);
-        let response: DataResponsePayloadSerde = Deserialize::deserialize(&mut deserializer)?;
-        let header_len = cursor.position() as usize;
+        // The piece data follows the bencoded header dictionary directly.
+        let (response, data): (DataResponsePayloadSerde, &[u8]) =
+            bencode::from_bytes_prefix_limited(payload, bencode::DecodeLimits::untrusted())?;
 
         match response.msg_type {
             x if x == MetadataMessageType::Request as u64 => {
@@ -210,9 +346,6 @@ impl MetadataFetcher {
             _ => {}
         }
 
-        let data = payload
-            .get(header_len..)
-            .context("Metadata message missing piece data")?;
         if data.is_empty() {
             bail!("Empty metadata piece received");
         }
@@ -265,7 +398,7 @@ impl MetadataFetcher {
         self.received_pieces.insert(response.piece);
 
         // Calculate how many pieces we expect and whether we're done.
-        let expected_piece_count = (metadata_size + NOT_LAST_PIECE_SIZE - 1) / NOT_LAST_PIECE_SIZE;
+        let expected_piece_count = metadata_size.div_ceil(NOT_LAST_PIECE_SIZE);
         let complete = self.received_pieces.len() >= expected_piece_count;
 
         if !complete {
@@ -321,6 +454,15 @@ impl PeerSessionHandler for MetadataFetcher {
                 let metadata_size = ext_payload
                     .metadata_size
                     .context("Metadata size was not received on extended handshake")?;
+                // The buffer below is sized by the peer; refuse sizes we would not decode anyway.
+                let max_size = bencode::DecodeLimits::untrusted().max_total_size;
+                if metadata_size > max_size as u64 {
+                    bail!(
+                        "Peer advertised metadata size {} above the {} byte limit",
+                        metadata_size,
+                        max_size
+                    );
+                }
                 self.total_size = Some(metadata_size as usize);
                 self.metadata_bytes = Some(vec![0u8; metadata_size as usize]);
 
@@ -350,6 +492,88 @@ impl PeerSessionHandler for MetadataFetcher {
     }
 }
 
+/// What a peer source of the magnet link turned up.
+enum Found {
+    /// Peers, each paired with the swarm hash to handshake under.
+    Peers(Vec<(tracker::Peer, Vec<u8>)>),
+    /// A verified torrent fetched from an exact source.
+    Torrent(Box<TorrentMetainfo>),
+}
+
+/// Resolve an `x.pe` address to the peers we can connect to. Only IPv4 addresses are
+/// usable; anything else is logged and skipped.
+pub fn resolve_peer(address: &PeerAddress) -> Vec<tracker::Peer> {
+    let host = address.host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = match (host, address.port).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            log_error!("MetadataFetcher", "Cannot resolve peer {}: {}", host, e);
+            return Vec::new();
+        }
+    };
+    addrs
+        .filter_map(|addr| match addr {
+            SocketAddr::V4(addr) => Some(tracker::Peer {
+                ip: *addr.ip(),
+                port: addr.port(),
+            }),
+            SocketAddr::V6(addr) => {
+                log_debug!("MetadataFetcher", "Skipping IPv6 peer {}", addr);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Download the .torrent file at an `xs` URL, check that it is the one the link names and
+/// add the link's trackers and web seeds.
+fn fetch_exact_source(magnet_link: &MagnetLink, url: &Url) -> anyhow::Result<TorrentMetainfo> {
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("unsupported exact source scheme '{}'", url.scheme());
+    }
+    let response = reqwest::blocking::get(url.clone())?.error_for_status()?;
+    let metainfo = magnet_link.merge_metainfo(&read_exact_source(response)?)?;
+    log_topics(magnet_link, &metainfo)?;
+    Ok(metainfo)
+}
+
+/// Decode an `xs` response body. The URL can point anywhere, so the body is read up to the
+/// untrusted size limit and decoded with [`bencode::DecodeLimits::untrusted`].
+fn read_exact_source(body: impl Read) -> anyhow::Result<TorrentMetainfo> {
+    let limits = bencode::DecodeLimits::untrusted();
+    let mut bytes = Vec::new();
+    body.take(limits.max_total_size as u64 + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.len() > limits.max_total_size {
+        bail!(
+            "exact source is larger than {} bytes",
+            limits.max_total_size
+        );
+    }
+    TorrentMetainfo::from_bytes_limited(&bytes, limits)
+}
+
+/// Log which topics of the magnet link the metadata answers; a link may name several
+/// torrents.
+fn log_topics(magnet_link: &MagnetLink, metainfo: &TorrentMetainfo) -> anyhow::Result<()> {
+    for hash in magnet_link.info_hashes()? {
+        if metainfo.has_info_hash(&hash) {
+            log_debug!(
+                "MetadataFetcher",
+                "Info hash verified: {}",
+                hex::encode(hash)
+            );
+        } else {
+            log_debug!(
+                "MetadataFetcher",
+                "Magnet topic {} names a different torrent",
+                hex::encode(hash)
+            );
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct PieceRequestPayloadSerde {
     pub msg_type: u64,
@@ -369,45 +593,60 @@ mod tests {
 
     #[test]
     fn test_piece_request_message() {
-        let payload = serde_bencode::to_bytes(&PieceRequestPayloadSerde {
+        let payload = bencode::to_bytes(&PieceRequestPayloadSerde {
             msg_type: MetadataMessageType::Request as u64,
             piece: 0,
         })
         .unwrap();
 
-        let deserialized: PieceRequestPayloadSerde = serde_bencode::from_bytes(&payload).unwrap();
+        let deserialized: PieceRequestPayloadSerde = bencode::from_bytes(&payload).unwrap();
         assert_eq!(deserialized.msg_type, 0);
         assert_eq!(deserialized.piece, 0);
     }
 
-    #[test]
-    fn test_bencode_decode_with_extra_data() {
-        let encoded = "d4:city4:test6:street4:teste_kekekekek".as_bytes();
-        let decoded: Address = serde_bencode::from_bytes(&encoded).unwrap();
-        assert_eq!(decoded.city, "test");
-        assert_eq!(decoded.street, "test");
-    }
-
     #[test]
     fn test_metadata_response_message_len() {
-        let payload = serde_bencode::to_bytes(&DataResponsePayloadSerde {
+        let mut payload = bencode::to_bytes(&DataResponsePayloadSerde {
             msg_type: MetadataMessageType::Data as u64,
             piece: 0,
             total_size: 1234,
         })
         .unwrap();
+        payload.extend_from_slice(b"piece data");
 
-        let mut cursor = Cursor::new(&payload);
-        let mut deserializer = Deserializer::new(&mut cursor);
-        let _response: DataResponsePayloadSerde =
-            Deserialize::deserialize(&mut deserializer).unwrap();
+        let (response, data): (DataResponsePayloadSerde, _) =
+            bencode::from_bytes_prefix(&payload).unwrap();
 
-        assert_eq!(cursor.position() as usize, payload.len());
+        assert_eq!(response.total_size, 1234);
+        assert_eq!(data, b"piece data");
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct Address {
-        city: String,
-        street: String,
+    #[test]
+    fn resolves_ipv4_peers_only() {
+        let peers = resolve_peer(&"127.0.0.1:6881".parse().unwrap());
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].to_string(), "127.0.0.1:6881");
+        assert!(resolve_peer(&"[::1]:6881".parse().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn rejects_oversized_and_deeply_nested_exact_sources() {
+        let max_size = bencode::DecodeLimits::untrusted().max_total_size;
+        let body = std::io::repeat(b'l').take(max_size as u64 * 2);
+        let Err(err) = read_exact_source(body) else {
+            panic!("oversized exact source accepted");
+        };
+        assert!(err.to_string().contains("larger than"), "{err}");
+
+        let nested = [
+            b"d4:info".to_vec(),
+            b"l".repeat(10_000),
+            b"e".repeat(10_001),
+        ]
+        .concat();
+        let Err(err) = read_exact_source(nested.as_slice()) else {
+            panic!("deeply nested exact source accepted");
+        };
+        assert!(format!("{err:#}").contains("nesting depth"), "{err:#}");
     }
 }
@@ -1,4 +1,5 @@
 pub mod connection;
+pub mod discovery;
 pub mod extension;
 pub mod message;
 pub mod metadata;
@@ -6,6 +7,7 @@ pub mod session;
 
 pub use connection::PeerConnection;
 pub use connection::{PeerCommand, PeerEvent, PeerStateSnapshot};
+pub use discovery::{DiscoveryPolicy, PeerSource};
 pub use extension::{ExtensionHandshakePayload, ExtensionMessage};
 pub use message::{HandshakeRequest, HandshakeResponse, PeerMessage, PeerMessageType};
 pub use session::{PeerSession, PeerSessionConfig, PeerSessionHandler, SessionControl};
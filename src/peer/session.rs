@@ -48,8 +48,8 @@ pub struct PeerSessionConfig {
     pub max_retries: u8,
 }
 
-impl PeerSessionConfig {
-    pub fn default() -> Self {
+impl Default for PeerSessionConfig {
+    fn default() -> Self {
         // Using more aggressive backoff for faster testing cycles.
         Self {
             backoff_base_secs: 1.0,
@@ -57,7 +57,9 @@ impl PeerSessionConfig {
             max_retries: 2,
         }
     }
+}
 
+impl PeerSessionConfig {
     pub fn aggressive() -> Self {
         Self {
             backoff_base_secs: 0.5,
@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+
+use super::TorrentFile;
+use crate::bencode::BencodeValue;
+use crate::utils;
+
+const MIN_PIECE_LENGTH: u64 = 16 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+/// Automatic piece length aims for at most this many pieces.
+const TARGET_PIECE_COUNT: u64 = 1500;
+
+/// Builds .torrent files from a file or a directory on disk.
+///
+/// A file produces a single-file torrent; a directory produces a multi-file torrent named
+/// after the directory, with its files in sorted path order.
+pub struct TorrentBuilder {
+    path: PathBuf,
+    piece_length: Option<u64>,
+    trackers: Vec<Vec<String>>,
+    web_seeds: Vec<String>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<i64>,
+    private: bool,
+    source: Option<String>,
+    threads: usize,
+}
+
+impl TorrentBuilder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            piece_length: None,
+            trackers: Vec::new(),
+            web_seeds: Vec::new(),
+            comment: None,
+            created_by: None,
+            creation_date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs() as i64),
+            private: false,
+            source: None,
+            threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+
+    /// Use a fixed piece length (a power of two) instead of choosing one from the total size.
+    pub fn piece_length(mut self, piece_length: u64) -> Self {
+        self.piece_length = Some(piece_length);
+        self
+    }
+
+    /// Add a tracker tier. The first tracker of the first tier becomes `announce`; the
+    /// tiers are written as `announce-list` when there is more than one tracker.
+    pub fn tier(mut self, trackers: Vec<String>) -> Self {
+        if !trackers.is_empty() {
+            self.trackers.push(trackers);
+        }
+        self
+    }
+
+    /// Add a tracker in a tier of its own.
+    pub fn tracker(self, url: impl Into<String>) -> Self {
+        self.tier(vec![url.into()])
+    }
+
+    /// Add a web seed (BEP-19 `url-list`).
+    pub fn web_seed(mut self, url: impl Into<String>) -> Self {
+        self.web_seeds.push(url.into());
+        self
+    }
+
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    pub fn created_by(mut self, created_by: impl Into<String>) -> Self {
+        self.created_by = Some(created_by.into());
+        self
+    }
+
+    /// Creation time in seconds since the Unix epoch; defaults to now, `None` omits it.
+    pub fn creation_date(mut self, creation_date: Option<i64>) -> Self {
+        self.creation_date = creation_date;
+        self
+    }
+
+    /// Set the BEP-27 private flag.
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Set the `source` tag, which private trackers use to give each site its own info hash.
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Number of threads used to hash pieces.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Hash the content and return the bencoded .torrent file.
+    pub fn build(&self) -> anyhow::Result<Vec<u8>> {
+        let name = file_name(&self.path)?;
+        let metadata = std::fs::metadata(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+
+        let (files, sources) = if metadata.is_dir() {
+            let mut entries = Vec::new();
+            collect_files(&self.path, &mut Vec::new(), &mut entries)?;
+            if entries.is_empty() {
+                bail!("{} contains no files", self.path.display());
+            }
+            let files = TorrentFile::layout(entries.iter().map(|(p, _, len)| (p.clone(), *len)))?;
+            let sources = entries.into_iter().map(|(_, source, _)| source).collect();
+            (files, sources)
+        } else {
+            let files = TorrentFile::layout([(vec![name.clone()], metadata.len())])?;
+            (files, vec![self.path.clone()])
+        };
+
+        let total_length = files.last().map(TorrentFile::end).unwrap_or(0);
+        let piece_length = match self.piece_length {
+            Some(len) if len == 0 || !len.is_power_of_two() => {
+                bail!("Piece length must be a power of two, got {}", len)
+            }
+            Some(len) => len,
+            None => auto_piece_length(total_length),
+        };
+        let pieces = hash_pieces(&files, &sources, piece_length, self.threads)?;
+
+        let mut info = BTreeMap::new();
+        info.insert(b"name".to_vec(), BencodeValue::from(name));
+        info.insert(
+            b"piece length".to_vec(),
+            BencodeValue::from(piece_length as i64),
+        );
+        info.insert(b"pieces".to_vec(), BencodeValue::from(pieces));
+        if metadata.is_dir() {
+            let entries = files
+                .iter()
+                .map(|file| {
+                    BencodeValue::from_iter([
+                        ("length", BencodeValue::from(file.length as i64)),
+                        ("path", string_list(&file.path)),
+                    ])
+                })
+                .collect::<Vec<_>>();
+            info.insert(b"files".to_vec(), BencodeValue::from(entries));
+        } else {
+            info.insert(b"length".to_vec(), BencodeValue::from(total_length as i64));
+        }
+        if self.private {
+            info.insert(b"private".to_vec(), BencodeValue::from(1));
+        }
+        if let Some(source) = &self.source {
+            info.insert(b"source".to_vec(), BencodeValue::from(source.as_str()));
+        }
+
+        let mut root = BTreeMap::new();
+        root.insert(b"info".to_vec(), BencodeValue::from(info));
+        if let Some(announce) = self.trackers.first().and_then(|tier| tier.first()) {
+            root.insert(b"announce".to_vec(), BencodeValue::from(announce.as_str()));
+        }
+        if self.trackers.iter().map(Vec::len).sum::<usize>() > 1 {
+            let tiers = self
+                .trackers
+                .iter()
+                .map(|tier| string_list(tier))
+                .collect::<Vec<_>>();
+            root.insert(b"announce-list".to_vec(), BencodeValue::from(tiers));
+        }
+        if !self.web_seeds.is_empty() {
+            root.insert(b"url-list".to_vec(), string_list(&self.web_seeds));
+        }
+        if let Some(comment) = &self.comment {
+            root.insert(b"comment".to_vec(), BencodeValue::from(comment.as_str()));
+        }
+        if let Some(created_by) = &self.created_by {
+            root.insert(
+                b"created by".to_vec(),
+                BencodeValue::from(created_by.as_str()),
+            );
+        }
+        if let Some(creation_date) = self.creation_date {
+            root.insert(b"creation date".to_vec(), BencodeValue::from(creation_date));
+        }
+
+        Ok(BencodeValue::from(root).encode())
+    }
+}
+
+/// Smallest power of two between 16 KiB and 16 MiB giving at most about 1500 pieces.
+pub fn auto_piece_length(total_length: u64) -> u64 {
+    let mut piece_length = MIN_PIECE_LENGTH;
+    while piece_length < MAX_PIECE_LENGTH
+        && total_length.div_ceil(piece_length) > TARGET_PIECE_COUNT
+    {
+        piece_length *= 2;
+    }
+    piece_length
+}
+
+fn string_list(items: &[String]) -> BencodeValue {
+    BencodeValue::List(items.iter().map(|item| item.as_str().into()).collect())
+}
+
+fn file_name(path: &Path) -> anyhow::Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .with_context(|| format!("{} has no UTF-8 file name", path.display()))
+}
+
+/// Recursively list the files under `dir` in sorted order as (relative path, source, length).
+/// Symlinks are skipped, so a link back to a parent directory cannot recurse forever.
+fn collect_files(
+    dir: &Path,
+    prefix: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, PathBuf, u64)>,
+) -> anyhow::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        if entry.file_type()?.is_symlink() {
+            continue;
+        }
+        let path = entry.path();
+        prefix.push(file_name(&path)?);
+        let metadata = std::fs::metadata(&path)?;
+        if metadata.is_dir() {
+            collect_files(&path, prefix, out)?;
+        } else {
+            out.push((prefix.clone(), path, metadata.len()));
+        }
+        prefix.pop();
+    }
+    Ok(())
+}
+
+/// SHA-1 every piece of the concatenated file data, splitting the pieces across `threads`.
+fn hash_pieces(
+    files: &[TorrentFile],
+    sources: &[PathBuf],
+    piece_length: u64,
+    threads: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let total_length = files.last().map(TorrentFile::end).unwrap_or(0);
+    let piece_count = total_length.div_ceil(piece_length) as usize;
+    let per_thread = piece_count.div_ceil(threads.max(1)).max(1);
+
+    let chunks = std::thread::scope(|scope| {
+        let handles = (0..piece_count)
+            .step_by(per_thread)
+            .map(|first| {
+                let last = (first + per_thread).min(piece_count);
+                scope.spawn(move || -> anyhow::Result<Vec<u8>> {
+                    let mut hashes = Vec::with_capacity((last - first) * 20);
+                    let mut buffer = Vec::new();
+                    let mut handles = (0..files.len()).map(|_| None).collect::<Vec<_>>();
+                    for index in first..last {
+                        let offset = index as u64 * piece_length;
+                        let length = piece_length.min(total_length - offset);
+                        buffer.resize(length as usize, 0);
+                        read_range(files, sources, &mut handles, offset, &mut buffer)?;
+                        hashes.extend_from_slice(&utils::sha1(&buffer));
+                    }
+                    Ok(hashes)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("hashing thread panicked"))
+            .collect::<anyhow::Result<Vec<_>>>()
+    })?;
+
+    Ok(chunks.concat())
+}
+
+/// Fill `buffer` with the torrent data starting at `offset`, opening each source file once
+/// and keeping it in `handles` for the following pieces.
+fn read_range(
+    files: &[TorrentFile],
+    sources: &[PathBuf],
+    handles: &mut [Option<File>],
+    offset: u64,
+    buffer: &mut [u8],
+) -> anyhow::Result<()> {
+    let end = offset + buffer.len() as u64;
+    for ((file, source), handle) in files.iter().zip(sources).zip(handles) {
+        if file.end() <= offset || file.offset >= end {
+            continue;
+        }
+        let start = offset.max(file.offset);
+        let stop = end.min(file.end());
+
+        let handle = match handle {
+            Some(handle) => handle,
+            None => handle.insert(
+                File::open(source)
+                    .with_context(|| format!("Failed to open {}", source.display()))?,
+            ),
+        };
+        handle.seek(SeekFrom::Start(start - file.offset))?;
+        handle
+            .read_exact(&mut buffer[(start - offset) as usize..(stop - offset) as usize])
+            .with_context(|| format!("{} changed while hashing", source.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent::TorrentMetainfo;
+
+    #[test]
+    fn picks_power_of_two_piece_lengths() {
+        assert_eq!(auto_piece_length(0), MIN_PIECE_LENGTH);
+        assert_eq!(auto_piece_length(1500 * MIN_PIECE_LENGTH), MIN_PIECE_LENGTH);
+        assert_eq!(
+            auto_piece_length(1500 * MIN_PIECE_LENGTH + 1),
+            2 * MIN_PIECE_LENGTH
+        );
+        assert_eq!(auto_piece_length(u64::MAX / 2), MAX_PIECE_LENGTH);
+    }
+
+    #[test]
+    fn builds_single_file_torrent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let data = (0..40_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(&path, &data).unwrap();
+
+        let bytes = TorrentBuilder::new(&path)
+            .piece_length(16384)
+            .tracker("http://tracker.example/announce")
+            .creation_date(None)
+            .threads(2)
+            .build()
+            .unwrap();
+        let metainfo = TorrentMetainfo::from_bytes(&bytes).unwrap();
+
+        assert_eq!(metainfo.name, "data.bin");
+        assert_eq!(metainfo.announce, "http://tracker.example/announce");
+        assert_eq!(metainfo.length, 40_000);
+        assert_eq!(metainfo.get_piece_count(), 3);
+        assert_eq!(
+            metainfo.get_piece_hash_bytes(2),
+            utils::sha1(&data[32768..]).as_slice()
+        );
+        assert_eq!(metainfo.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn builds_multi_file_torrent_with_pieces_across_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("album");
+        std::fs::create_dir_all(root.join("b")).unwrap();
+        std::fs::write(root.join("a.txt"), vec![1u8; 20_000]).unwrap();
+        std::fs::write(root.join("b").join("c.txt"), vec![2u8; 30_000]).unwrap();
+
+        let bytes = TorrentBuilder::new(&root)
+            .piece_length(16384)
+            .tier(vec!["http://a/announce".into(), "http://b/announce".into()])
+            .tracker("udp://c:80")
+            .web_seed("http://seed/")
+            .private(true)
+            .source("SITE")
+            .build()
+            .unwrap();
+        let metainfo = TorrentMetainfo::from_bytes(&bytes).unwrap();
+
+        assert!(metainfo.is_multi_file());
+        assert_eq!(metainfo.name, "album");
+        let paths = metainfo
+            .files()
+            .iter()
+            .map(|f| f.path.join("/"))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["a.txt", "b/c.txt"]);
+        assert_eq!(metainfo.tracker_tiers().len(), 2);
+
+        let mut data = vec![1u8; 20_000];
+        data.extend(vec![2u8; 30_000]);
+        assert_eq!(
+            metainfo.get_piece_hash_bytes(1),
+            utils::sha1(&data[16384..32768]).as_slice()
+        );
+
+        // Changing the source tag changes the info hash.
+        let other = TorrentBuilder::new(&root)
+            .piece_length(16384)
+            .private(true)
+            .source("OTHER")
+            .build()
+            .unwrap();
+        assert_ne!(
+            TorrentMetainfo::from_bytes(&other).unwrap().info_hash,
+            metainfo.info_hash
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_symlinks_instead_of_following_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("album");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub").join("a.txt"), b"data").unwrap();
+        std::os::unix::fs::symlink(&root, root.join("sub").join("loop")).unwrap();
+
+        let bytes = TorrentBuilder::new(&root).build().unwrap();
+        let metainfo = TorrentMetainfo::from_bytes(&bytes).unwrap();
+        let paths = metainfo
+            .files()
+            .iter()
+            .map(|f| f.path.join("/"))
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["sub/a.txt"]);
+    }
+
+    #[test]
+    fn rejects_invalid_piece_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("x");
+        std::fs::write(&path, b"x").unwrap();
+        assert!(TorrentBuilder::new(&path)
+            .piece_length(1000)
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+
+use super::{MagnetLink, MetaVersion, TorrentMetainfo};
+use crate::log_debug;
+
+/// On-disk cache of info dictionaries fetched for magnet links, so resolving the same link
+/// again does not need a metadata exchange.
+///
+/// Each info dictionary is stored as-is in `<info hash>.info`, once under each hash a magnet
+/// link may name it by (both, for a hybrid torrent). Entries are checked against the link
+/// when loaded, so a corrupt or tampered entry is never used.
+#[derive(Debug, Clone)]
+pub struct MetadataCache {
+    dir: PathBuf,
+}
+
+impl MetadataCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_CACHE_HOME/codecrafters-bittorrent/metadata`, falling back to `~/.cache`.
+    /// `None` if neither variable is set.
+    pub fn default_location() -> Option<Self> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(Self::new(
+            base.join(env!("CARGO_PKG_NAME")).join("metadata"),
+        ))
+    }
+
+    /// The torrent for `magnet_link`, if the info dictionary of one of its topics is cached.
+    pub fn load(&self, magnet_link: &MagnetLink) -> Option<TorrentMetainfo> {
+        for hash in magnet_link.info_hashes().ok()? {
+            let path = self.path(&hash);
+            let Ok(info_bytes) = std::fs::read(&path) else {
+                continue;
+            };
+            match magnet_link.to_metainfo(&info_bytes) {
+                Ok(metainfo) => return Some(metainfo),
+                Err(e) => log_debug!(
+                    "MetadataCache",
+                    "Ignoring bad entry {}: {:#}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        None
+    }
+
+    /// Store the info dictionary of `metainfo`.
+    pub fn store(&self, metainfo: &TorrentMetainfo) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+
+        let mut hashes = Vec::new();
+        if metainfo.version != MetaVersion::V2 {
+            hashes.push(metainfo.info_hash.clone());
+        }
+        if let Some(hash) = metainfo.info_hash_v2 {
+            hashes.push(hash.to_vec());
+        }
+        for hash in hashes {
+            // Write then rename, so a concurrent load never sees half an entry.
+            let path = self.path(&hash);
+            let partial = path.with_extension("partial");
+            std::fs::write(&partial, metainfo.info_bytes())?;
+            std::fs::rename(&partial, &path)?;
+        }
+        Ok(())
+    }
+
+    fn path(&self, info_hash: &[u8]) -> PathBuf {
+        self.dir.join(format!("{}.info", hex::encode(info_hash)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_and_loads_info_dictionaries() {
+        let torrent = std::fs::read("sample.torrent").unwrap();
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        let magnet = MagnetLink::from_metainfo(&metainfo).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cache = MetadataCache::new(dir.path());
+
+        assert!(cache.load(&magnet).is_none());
+        cache.store(&metainfo).unwrap();
+        let loaded = cache.load(&magnet).unwrap();
+        assert_eq!(loaded.info_bytes(), metainfo.info_bytes());
+        assert_eq!(loaded.announce, metainfo.announce);
+
+        // An entry that does not hash to its name is ignored.
+        let entry = dir
+            .path()
+            .join(format!("{}.info", metainfo.get_info_hash_hex()));
+        std::fs::write(entry, b"d4:name1:ae").unwrap();
+        assert!(cache.load(&magnet).is_none());
+    }
+}
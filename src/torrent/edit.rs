@@ -0,0 +1,171 @@
+use super::TorrentMetainfo;
+
+/// Changes to the parts of a torrent outside its info dictionary: trackers, web seeds,
+/// comment and created-by. Applying an edit never touches the info dictionary, so the info
+/// hash stays the same.
+#[derive(Debug, Clone, Default)]
+pub struct TorrentEdit {
+    replace_trackers: bool,
+    tiers: Vec<Vec<String>>,
+    removed_trackers: Vec<String>,
+    replace_web_seeds: bool,
+    web_seeds: Vec<String>,
+    removed_web_seeds: Vec<String>,
+    comment: Option<Option<String>>,
+    created_by: Option<Option<String>>,
+}
+
+impl TorrentEdit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a tracker tier after the existing ones. Trackers the torrent already has are
+    /// skipped.
+    pub fn tier(mut self, trackers: Vec<String>) -> Self {
+        if !trackers.is_empty() {
+            self.tiers.push(trackers);
+        }
+        self
+    }
+
+    /// Add a tracker in a tier of its own.
+    pub fn tracker(self, url: impl Into<String>) -> Self {
+        self.tier(vec![url.into()])
+    }
+
+    /// Remove a tracker from whichever tier holds it.
+    pub fn remove_tracker(mut self, url: impl Into<String>) -> Self {
+        self.removed_trackers.push(url.into());
+        self
+    }
+
+    /// Drop all existing trackers, keeping only the tiers added by this edit.
+    pub fn replace_trackers(mut self) -> Self {
+        self.replace_trackers = true;
+        self
+    }
+
+    /// Add a web seed (BEP-19 `url-list`).
+    pub fn web_seed(mut self, url: impl Into<String>) -> Self {
+        self.web_seeds.push(url.into());
+        self
+    }
+
+    pub fn remove_web_seed(mut self, url: impl Into<String>) -> Self {
+        self.removed_web_seeds.push(url.into());
+        self
+    }
+
+    /// Drop all existing web seeds, keeping only the ones added by this edit.
+    pub fn replace_web_seeds(mut self) -> Self {
+        self.replace_web_seeds = true;
+        self
+    }
+
+    /// Set the comment; `None` removes it.
+    pub fn comment(mut self, comment: Option<String>) -> Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    /// Set `created by`; `None` removes it.
+    pub fn created_by(mut self, created_by: Option<String>) -> Self {
+        self.created_by = Some(created_by);
+        self
+    }
+
+    pub fn apply(&self, metainfo: &mut TorrentMetainfo) {
+        let edits_trackers =
+            self.replace_trackers || !self.tiers.is_empty() || !self.removed_trackers.is_empty();
+        if edits_trackers {
+            let mut tiers = if self.replace_trackers {
+                Vec::new()
+            } else {
+                metainfo.tracker_tiers()
+            };
+            for tier in &mut tiers {
+                tier.retain(|url| !self.removed_trackers.contains(url));
+            }
+            for tier in &self.tiers {
+                let new = tier
+                    .iter()
+                    .filter(|url| !tiers.iter().flatten().any(|existing| existing == *url))
+                    .cloned()
+                    .collect();
+                tiers.push(new);
+            }
+            metainfo.set_tracker_tiers(tiers);
+        }
+
+        if self.replace_web_seeds {
+            metainfo.url_list.clear();
+        }
+        metainfo
+            .url_list
+            .retain(|url| !self.removed_web_seeds.contains(url));
+        for url in &self.web_seeds {
+            if !metainfo.url_list.contains(url) {
+                metainfo.url_list.push(url.clone());
+            }
+        }
+
+        if let Some(comment) = &self.comment {
+            metainfo.comment = comment.clone();
+        }
+        if let Some(created_by) = &self.created_by {
+            metainfo.created_by = created_by.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TORRENT: &[u8] = b"d8:announce2:t113:announce-listll2:t12:t2el2:t3ee7:comment3:old5:nodesll4:host1:1ee4:infod4:name3:abc6:lengthi3e12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae8:url-list2:w1e";
+
+    #[test]
+    fn edits_trackers_and_keeps_the_info_hash() {
+        let mut metainfo = TorrentMetainfo::from_bytes(TORRENT).unwrap();
+        TorrentEdit::new()
+            .remove_tracker("t1")
+            .tier(vec!["t3".into(), "t4".into()])
+            .web_seed("w2")
+            .comment(None)
+            .created_by(Some("me".into()))
+            .apply(&mut metainfo);
+
+        let edited = TorrentMetainfo::from_bytes(&metainfo.to_bytes().unwrap()).unwrap();
+        assert_eq!(edited.info_hash, metainfo.info_hash);
+        assert_eq!(edited.info_bytes(), metainfo.info_bytes());
+        assert_eq!(edited.announce, "t2");
+        assert_eq!(
+            edited.tracker_tiers(),
+            vec![vec!["t2"], vec!["t3"], vec!["t4"]]
+        );
+        assert_eq!(edited.url_list, vec!["w1", "w2"]);
+        assert_eq!(edited.comment, None);
+        assert_eq!(edited.created_by.as_deref(), Some("me"));
+        // Keys the editor does not know about survive.
+        assert!(edited
+            .to_bytes()
+            .unwrap()
+            .windows(7)
+            .any(|w| w == b"5:nodes"));
+    }
+
+    #[test]
+    fn replacing_trackers_drops_the_old_ones() {
+        let mut metainfo = TorrentMetainfo::from_bytes(TORRENT).unwrap();
+        TorrentEdit::new()
+            .replace_trackers()
+            .tracker("new")
+            .replace_web_seeds()
+            .apply(&mut metainfo);
+
+        assert_eq!(metainfo.announce, "new");
+        assert!(metainfo.announce_list.is_empty());
+        assert!(metainfo.url_list.is_empty());
+    }
+}
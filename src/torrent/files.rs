@@ -0,0 +1,157 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+
+use super::merkle::Hash256;
+
+/// A file inside a torrent, located by its byte range in the concatenated torrent data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentFile {
+    /// Path components relative to the download root. For single-file torrents this is just
+    /// the torrent name.
+    pub path: Vec<String>,
+    pub length: u64,
+    /// Offset of the first byte of the file in the torrent data.
+    pub offset: u64,
+    /// Root of the file's SHA-256 merkle tree (v2 torrents, non-empty files only).
+    pub pieces_root: Option<Hash256>,
+    /// BEP-47 padding file: part of the piece layout but never written to disk.
+    pub padding: bool,
+    /// Hex MD5 of the file contents, if the creator included one.
+    pub md5sum: Option<String>,
+}
+
+impl TorrentFile {
+    /// Build the file list, assigning offsets in order. Every path component is checked with
+    /// [`check_path_component`].
+    pub fn layout(
+        entries: impl IntoIterator<Item = (Vec<String>, u64)>,
+    ) -> anyhow::Result<Vec<Self>> {
+        Self::layout_with(
+            entries
+                .into_iter()
+                .map(|(path, length)| (path, length, None)),
+            None,
+        )
+    }
+
+    /// Build the file list of a v2 torrent, where every non-empty file starts on a piece
+    /// boundary (BEP-52) so that no piece spans two files.
+    pub fn layout_aligned(
+        entries: impl IntoIterator<Item = (Vec<String>, u64, Option<Hash256>)>,
+        piece_length: u64,
+    ) -> anyhow::Result<Vec<Self>> {
+        Self::layout_with(entries, Some(piece_length))
+    }
+
+    fn layout_with(
+        entries: impl IntoIterator<Item = (Vec<String>, u64, Option<Hash256>)>,
+        alignment: Option<u64>,
+    ) -> anyhow::Result<Vec<Self>> {
+        let mut end = 0u64;
+        let mut files = Vec::new();
+        for (path, length, pieces_root) in entries {
+            if path.is_empty() {
+                bail!("Torrent file entry has an empty path");
+            }
+            for component in &path {
+                check_path_component(component)?;
+            }
+            let offset = match alignment {
+                Some(piece_length) if length > 0 => end
+                    .checked_next_multiple_of(piece_length)
+                    .context("Torrent file lengths overflow")?,
+                _ => end,
+            };
+            files.push(TorrentFile {
+                path,
+                length,
+                offset,
+                pieces_root,
+                padding: false,
+                md5sum: None,
+            });
+            end = offset
+                .checked_add(length)
+                .context("Torrent file lengths overflow")?;
+        }
+        Ok(files)
+    }
+
+    /// Path relative to the download root.
+    pub fn relative_path(&self) -> PathBuf {
+        self.path.iter().collect()
+    }
+
+    /// Offset one past the last byte of the file in the torrent data.
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+}
+
+/// Reject path components that could escape the download directory: empty components, `.`
+/// and `..`, and anything containing a path separator or NUL (which also rules out absolute
+/// paths and Windows drive prefixes).
+pub fn check_path_component(component: &str) -> anyhow::Result<()> {
+    if component.is_empty() || component == "." || component == ".." {
+        bail!("Unsafe path component in torrent: {:?}", component);
+    }
+    if component.contains(['/', '\\', '\0', ':']) {
+        bail!("Unsafe path component in torrent: {:?}", component);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(components: &[&str]) -> Vec<String> {
+        components.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn assigns_consecutive_offsets() {
+        let files =
+            TorrentFile::layout([(path(&["a.txt"]), 5), (path(&["dir", "b.bin"]), 7)]).unwrap();
+        assert_eq!(files[0].offset, 0);
+        assert_eq!(files[1].offset, 5);
+        assert_eq!(files[1].end(), 12);
+        assert_eq!(files[1].relative_path(), PathBuf::from("dir").join("b.bin"));
+    }
+
+    #[test]
+    fn aligned_layout_starts_files_on_piece_boundaries() {
+        let files = TorrentFile::layout_aligned(
+            [
+                (path(&["a"]), 5, Some([1; 32])),
+                (path(&["empty"]), 0, None),
+                (path(&["b"]), 20, Some([2; 32])),
+            ],
+            16,
+        )
+        .unwrap();
+        let offsets = files.iter().map(|f| f.offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 5, 16]);
+        assert_eq!(files[2].end(), 36);
+    }
+
+    #[test]
+    fn rejects_paths_escaping_the_download_root() {
+        for bad in [
+            path(&["..", "etc", "passwd"]),
+            path(&["dir", ""]),
+            path(&["/etc/passwd"]),
+            path(&["C:", "x"]),
+            path(&["a\\..\\b"]),
+            path(&["."]),
+            vec![],
+        ] {
+            assert!(
+                TorrentFile::layout([(bad.clone(), 1)]).is_err(),
+                "{:?}",
+                bad
+            );
+        }
+    }
+}
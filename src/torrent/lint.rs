@@ -0,0 +1,371 @@
+//! Static checks of a .torrent file, reporting every problem found rather than stopping at
+//! the first one like [`TorrentMetainfo::from_bytes`] does.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use reqwest::Url;
+
+use super::files::check_path_component;
+use super::TorrentMetainfo;
+use crate::bencode::{self, BencodeRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The torrent cannot be used as is.
+    Error,
+    /// The torrent works here but is unusual or may break elsewhere.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub severity: Severity,
+    /// Where the problem is, as a [`bencode::select`] path (`$` for the whole file).
+    pub at: String,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.at, self.message)
+    }
+}
+
+/// Tracker URL schemes we can announce to.
+const TRACKER_SCHEMES: [&str; 3] = ["http", "https", "udp"];
+
+/// Check a .torrent file, returning the problems found in file order.
+pub fn lint(bytes: &[u8]) -> Vec<LintIssue> {
+    let mut lint = Lint::default();
+    let root = match bencode::parse_ref(bytes) {
+        Ok(root) => root,
+        Err(e) => {
+            lint.error("$", format!("not valid bencode: {}", e));
+            return lint.issues;
+        }
+    };
+    if let Err(e) = bencode::parse_bytes_strict(bytes.to_vec()) {
+        lint.warning(
+            "$",
+            format!("not canonical bencode, so re-encoding changes it: {}", e),
+        );
+    }
+    if root.as_dict().is_none() {
+        lint.error("$", "top level is not a dictionary");
+        return lint.issues;
+    }
+
+    lint.check_trackers(&root);
+    match root.get("info") {
+        Some(info) if info.as_dict().is_some() => lint.check_info(info),
+        Some(_) => lint.error("$.info", "not a dictionary"),
+        None => lint.error("$", "missing required key 'info'"),
+    }
+
+    // Anything the checks above do not cover still stops the torrent from loading.
+    if !lint.has_errors() {
+        if let Err(e) = TorrentMetainfo::from_bytes(bytes) {
+            lint.error("$", format!("{:#}", e));
+        }
+    }
+    lint.issues
+}
+
+#[derive(Default)]
+struct Lint {
+    issues: Vec<LintIssue>,
+}
+
+impl Lint {
+    fn push(&mut self, severity: Severity, at: &str, message: impl Into<String>) {
+        self.issues.push(LintIssue {
+            severity,
+            at: at.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, at: &str, message: impl Into<String>) {
+        self.push(Severity::Error, at, message);
+    }
+
+    fn warning(&mut self, at: &str, message: impl Into<String>) {
+        self.push(Severity::Warning, at, message);
+    }
+
+    fn has_errors(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| issue.severity == Severity::Error)
+    }
+
+    fn check_trackers(&mut self, root: &BencodeRef) {
+        let mut urls = Vec::new();
+        match root.get("announce").map(BencodeRef::as_str) {
+            Some(Some(url)) => urls.push(("$.announce".to_string(), url)),
+            Some(None) => self.error("$.announce", "not a UTF-8 string"),
+            None => {}
+        }
+        if let Some(tiers) = root.get("announce-list") {
+            let Some(tiers) = tiers.as_list() else {
+                self.error("$.announce-list", "not a list of tiers");
+                return;
+            };
+            for (t, tier) in tiers.iter().enumerate() {
+                for (i, url) in tier.as_list().unwrap_or_default().iter().enumerate() {
+                    let at = format!("$.announce-list[{}][{}]", t, i);
+                    match url.as_str() {
+                        Some(url) => urls.push((at, url)),
+                        None => self.error(&at, "not a UTF-8 string"),
+                    }
+                }
+            }
+        }
+
+        if urls.is_empty() && root.get("url-list").is_none() {
+            self.warning(
+                "$",
+                "no trackers or web seeds; peers can only be found via DHT",
+            );
+        }
+        for (at, url) in urls {
+            match Url::parse(url) {
+                Ok(parsed) if TRACKER_SCHEMES.contains(&parsed.scheme()) => {}
+                Ok(parsed) => self.warning(
+                    &at,
+                    format!("unsupported tracker scheme '{}': {}", parsed.scheme(), url),
+                ),
+                Err(e) => self.error(&at, format!("invalid tracker URL {:?}: {}", url, e)),
+            }
+        }
+    }
+
+    fn check_info(&mut self, info: &BencodeRef) {
+        let name = match info.get("name").map(BencodeRef::as_str) {
+            Some(Some(name)) => Some(name),
+            Some(None) => {
+                self.error("$.info.name", "not a UTF-8 string");
+                None
+            }
+            None => {
+                self.error("$.info", "missing required key 'name'");
+                None
+            }
+        };
+        if let Some(name) = name {
+            self.check_path_component("$.info.name", name);
+        }
+
+        let piece_length = match info.get("piece length").map(BencodeRef::as_u64) {
+            Some(Some(0)) | Some(None) => {
+                self.error("$.info.piece length", "not a positive integer");
+                None
+            }
+            Some(Some(piece_length)) => Some(piece_length),
+            None => {
+                self.error("$.info", "missing required key 'piece length'");
+                None
+            }
+        };
+        if let Some(piece_length) = piece_length.filter(|pl| !pl.is_power_of_two()) {
+            self.warning(
+                "$.info.piece length",
+                format!("{} is not a power of two", piece_length),
+            );
+        }
+
+        let v2 = info.get("meta version").and_then(BencodeRef::as_int) == Some(2);
+        let length = self.check_files(info, v2);
+        match info.get("pieces").map(BencodeRef::as_bytes) {
+            Some(Some(pieces)) => {
+                if pieces.len() % 20 != 0 {
+                    self.error(
+                        "$.info.pieces",
+                        format!("length {} is not a multiple of 20", pieces.len()),
+                    );
+                } else if let (Some(length), Some(piece_length)) = (length, piece_length) {
+                    let expected = length.div_ceil(piece_length);
+                    if pieces.len() as u64 / 20 != expected {
+                        self.error(
+                            "$.info.pieces",
+                            format!(
+                                "{} piece hashes, but {} bytes in pieces of {} need {}",
+                                pieces.len() / 20,
+                                length,
+                                piece_length,
+                                expected
+                            ),
+                        );
+                    }
+                }
+            }
+            Some(None) => self.error("$.info.pieces", "not a byte string"),
+            None if !v2 => self.error("$.info", "missing required key 'pieces'"),
+            None => {}
+        }
+    }
+
+    /// Check the v1 `length`/`files` keys, returning the total length when it is known.
+    fn check_files(&mut self, info: &BencodeRef, v2: bool) -> Option<u64> {
+        match (info.get("length"), info.get("files")) {
+            (Some(_), Some(_)) => {
+                self.error("$.info", "has both 'length' and 'files'");
+                None
+            }
+            (Some(length), None) => {
+                let length = length.as_u64();
+                if length.is_none() {
+                    self.error("$.info.length", "not a non-negative integer");
+                }
+                length
+            }
+            (None, Some(files)) => self.check_file_list(files),
+            (None, None) if v2 => None,
+            (None, None) => {
+                self.error("$.info", "missing required key 'length' or 'files'");
+                None
+            }
+        }
+    }
+
+    fn check_file_list(&mut self, files: &BencodeRef) -> Option<u64> {
+        let Some(files) = files.as_list() else {
+            self.error("$.info.files", "not a list");
+            return None;
+        };
+        if files.is_empty() {
+            self.error("$.info.files", "is empty");
+        }
+
+        let mut total = Some(0u64);
+        let mut seen = HashSet::new();
+        let mut seen_folded = HashSet::new();
+        for (index, file) in files.iter().enumerate() {
+            let at = format!("$.info.files[{}]", index);
+            let length = file.get("length").and_then(BencodeRef::as_u64);
+            if length.is_none() {
+                self.error(&at, "missing or invalid 'length'");
+            }
+            total = total.zip(length).and_then(|(a, b)| a.checked_add(b));
+
+            let components = file
+                .get("path")
+                .and_then(BencodeRef::as_list)
+                .unwrap_or_default()
+                .iter()
+                .map(BencodeRef::as_str)
+                .collect::<Option<Vec<_>>>();
+            let Some(components) = components.filter(|c| !c.is_empty()) else {
+                self.error(&at, "missing, empty or non-UTF-8 'path'");
+                continue;
+            };
+            for (i, component) in components.iter().enumerate() {
+                self.check_path_component(&format!("{}.path[{}]", at, i), component);
+            }
+
+            let path = components.join("/");
+            if !seen.insert(path.clone()) {
+                self.error(&at, format!("duplicate path {}", path));
+            } else if !seen_folded.insert(path.to_lowercase()) {
+                self.warning(
+                    &at,
+                    format!("{} differs from another path only in case", path),
+                );
+            }
+        }
+        total
+    }
+
+    fn check_path_component(&mut self, at: &str, component: &str) {
+        if let Err(e) = check_path_component(component) {
+            self.error(at, e.to_string());
+        } else if is_reserved_name(component) {
+            self.warning(
+                at,
+                format!("{:?} is a reserved file name on Windows", component),
+            );
+        }
+    }
+}
+
+/// Windows device names, which cannot be used as file names even with an extension.
+fn is_reserved_name(component: &str) -> bool {
+    const RESERVED: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+    let stem = component
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    if RESERVED.contains(&stem.as_str()) {
+        return true;
+    }
+    match stem
+        .strip_prefix("COM")
+        .or_else(|| stem.strip_prefix("LPT"))
+    {
+        Some(digit) => matches!(digit.as_bytes(), [b'1'..=b'9']),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(torrent: &[u8]) -> Vec<String> {
+        lint(torrent).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn accepts_a_well_formed_torrent() {
+        let torrent = std::fs::read("sample.torrent").unwrap();
+        assert_eq!(messages(&torrent), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_piece_problems() {
+        let torrent = b"d8:announce8:http://t4:infod6:lengthi9e4:name1:a12:piece lengthi3e6:pieces21:aaaaaaaaaaaaaaaaaaaaaee";
+        assert_eq!(
+            messages(torrent),
+            vec![
+                "warning: $.info.piece length: 3 is not a power of two",
+                "error: $.info.pieces: length 21 is not a multiple of 20",
+            ]
+        );
+
+        let torrent = b"d8:announce8:http://t4:infod6:lengthi9e4:name1:a12:piece lengthi4e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        assert_eq!(
+            messages(torrent),
+            vec!["error: $.info.pieces: 1 piece hashes, but 9 bytes in pieces of 4 need 3"]
+        );
+    }
+
+    #[test]
+    fn reports_bad_paths_and_missing_keys() {
+        let torrent = b"d8:announce8:ftp://t/4:infod5:filesld6:lengthi1e4:pathl2:..1:aeed6:lengthi1e4:pathl7:con.txteed6:lengthi1e4:pathl1:beed6:lengthi1e4:pathl1:beed6:lengthi1e4:pathl1:Beee12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        assert_eq!(
+            messages(torrent),
+            vec![
+                "warning: $.announce: unsupported tracker scheme 'ftp': ftp://t/",
+                "error: $.info: missing required key 'name'",
+                "error: $.info.files[0].path[0]: Unsafe path component in torrent: \"..\"",
+                "warning: $.info.files[1].path[0]: \"con.txt\" is a reserved file name on Windows",
+                "error: $.info.files[3]: duplicate path b",
+                "warning: $.info.files[4]: B differs from another path only in case",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_non_canonical_encoding() {
+        let torrent = b"d4:infod4:name1:a6:lengthi1e12:piece lengthi16e6:pieces20:aaaaaaaaaaaaaaaaaaaae8:announce8:http://te";
+        let issues = messages(torrent);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].starts_with("warning: $: not canonical bencode"));
+    }
+}
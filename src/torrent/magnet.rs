@@ -1,10 +1,15 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Ok};
+use data_encoding::BASE32;
 use reqwest::Url;
 
+use super::{MetaVersion, TorrentMetainfo};
+use crate::utils::url_encode;
+
 /// Parsed representation of a BitTorrent magnet URI.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MagnetLink {
     /// All exact-topic identifiers (e.g. btih, btmh).
     pub exact_topics: Vec<ExactTopic>,
@@ -38,10 +43,11 @@ pub struct MagnetLink {
 }
 
 /// `xt` values we care about.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ExactTopic {
-    /// `xt=urn:btih:<infohash>` (SHA-1 v1 infohash, hex or base32 string).
-    Btih(String),
+    /// `xt=urn:btih:<infohash>`: the SHA-1 v1 info hash, given as 40 hex or 32 base32
+    /// characters.
+    Btih([u8; 20]),
 
     /// `xt=urn:btmh:<tagged-infohash>` (v2 “multihash”).
     Btmh(String),
@@ -51,23 +57,219 @@ pub enum ExactTopic {
 }
 
 impl ExactTopic {
-    /// Get the raw infohash string, if this is a btih or btmh topic.
-    pub fn get_hash(&self) -> Option<&str> {
+    /// Decode the topic into the info hash bytes it names: the 20-byte SHA-1 for btih, the
+    /// 32-byte SHA-256 for btmh. `None` for topics that are not BitTorrent info hashes.
+    pub fn info_hash(&self) -> anyhow::Result<Option<Vec<u8>>> {
         match self {
-            ExactTopic::Btih(s) | ExactTopic::Btmh(s) => Some(s),
-            ExactTopic::Other(_) => None,
+            ExactTopic::Btih(hash) => Ok(Some(hash.to_vec())),
+            ExactTopic::Btmh(s) => {
+                let multihash = hex::decode(s).context("invalid btmh hex")?;
+                // Multihash header: 0x12 = sha2-256, 0x20 = 32-byte digest.
+                match multihash.strip_prefix(&[0x12, 0x20][..]) {
+                    Some(hash) if hash.len() == 32 => Ok(Some(hash.to_vec())),
+                    _ => bail!("btmh must be a sha2-256 multihash"),
+                }
+            }
+            ExactTopic::Other(_) => Ok(None),
         }
     }
 }
 
 /// Bootstrap peer address from `x.pe`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PeerAddress {
     pub host: String,
     pub port: u16,
 }
 
+impl FromStr for PeerAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        parse_peer(s)
+    }
+}
+
 impl MagnetLink {
+    /// Trackers as BEP-12 tiers. Magnet links carry no tier information, so every `tr`
+    /// tracker goes into a single tier.
+    pub fn tracker_tiers(&self) -> Vec<Vec<String>> {
+        if self.trackers.is_empty() {
+            return Vec::new();
+        }
+        vec![self.trackers.iter().map(Url::to_string).collect()]
+    }
+
+    /// Info hashes of all btih/btmh topics. A hybrid torrent may be linked by both.
+    pub fn info_hashes(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut hashes = Vec::new();
+        for topic in &self.exact_topics {
+            hashes.extend(topic.info_hash()?);
+        }
+        Ok(hashes)
+    }
+
+    /// The distinct 20-byte hashes to announce and handshake under, in topic order: v1
+    /// info hashes as they are, v2 info hashes truncated. A link may name several torrents,
+    /// or one hybrid torrent by both of its hashes.
+    pub fn swarm_hashes(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut hashes: Vec<Vec<u8>> = Vec::new();
+        for hash in self.info_hashes()? {
+            let hash = hash[..20].to_vec();
+            if !hashes.contains(&hash) {
+                hashes.push(hash);
+            }
+        }
+        if hashes.is_empty() {
+            bail!("magnet link has no btih or btmh topic");
+        }
+        Ok(hashes)
+    }
+
+    /// Magnet link for a torrent: its btih and/or btmh info hashes, name, exact length,
+    /// trackers and web seeds.
+    pub fn from_metainfo(metainfo: &TorrentMetainfo) -> anyhow::Result<MagnetLink> {
+        let mut exact_topics = Vec::new();
+        if metainfo.version != MetaVersion::V2 {
+            let hash = metainfo.info_hash.as_slice().try_into()?;
+            exact_topics.push(ExactTopic::Btih(hash));
+        }
+        if let Some(hash) = metainfo.info_hash_v2 {
+            exact_topics.push(ExactTopic::Btmh(format!("1220{}", hex::encode(hash))));
+        }
+
+        let trackers = metainfo
+            .tracker_tiers()
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .map(Url::parse)
+            .collect::<Result<_, _>>()
+            .context("invalid tracker URL in torrent")?;
+        let web_seeds = metainfo
+            .url_list
+            .iter()
+            .map(String::as_str)
+            .map(Url::parse)
+            .collect::<Result<_, _>>()
+            .context("invalid web seed URL in torrent")?;
+        Ok(MagnetLink {
+            exact_topics,
+            display_name: Some(metainfo.name.clone()),
+            trackers,
+            web_seeds,
+            acceptable_sources: Vec::new(),
+            exact_sources: Vec::new(),
+            peers: Vec::new(),
+            length: Some(metainfo.length),
+            select_only: None,
+            other_params: HashMap::new(),
+        })
+    }
+
+    /// Check metadata fetched for this link against its exact length (`xl`), if it has one.
+    pub fn check_length(&self, metainfo: &TorrentMetainfo) -> anyhow::Result<()> {
+        match self.length {
+            Some(expected) if expected != metainfo.length => bail!(
+                "metadata describes {} bytes, but the magnet link says xl={}",
+                metainfo.length,
+                expected
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Complete torrent for an info dictionary fetched for this link: the info bytes exactly
+    /// as given, with the link's trackers and web seeds. Fails unless the info dictionary is
+    /// one the link names and has the link's exact length.
+    pub fn to_metainfo(&self, info_bytes: &[u8]) -> anyhow::Result<TorrentMetainfo> {
+        let mut metainfo = TorrentMetainfo::from_info_bytes(String::new(), info_bytes)?;
+        let info_hashes = self.info_hashes()?;
+        if !info_hashes.iter().any(|hash| metainfo.has_info_hash(hash)) {
+            bail!("info dictionary is not one the magnet link names");
+        }
+        self.check_length(&metainfo)?;
+
+        metainfo.set_tracker_tiers(self.tracker_tiers());
+        metainfo.url_list = self.web_seeds.iter().map(Url::to_string).collect();
+        Ok(metainfo)
+    }
+
+    /// Complete torrent for a .torrent file fetched from one of the link's `xs` sources: the
+    /// same checks and trackers as [`to_metainfo`](Self::to_metainfo), followed by the
+    /// trackers and web seeds of the file itself that the link does not already list.
+    pub fn merge_metainfo(&self, source: &TorrentMetainfo) -> anyhow::Result<TorrentMetainfo> {
+        let mut metainfo = self.to_metainfo(source.info_bytes())?;
+
+        let mut tiers = metainfo.tracker_tiers();
+        for tier in source.tracker_tiers() {
+            let tier = tier
+                .into_iter()
+                .filter(|url| !tiers.iter().flatten().any(|known| known == url))
+                .collect::<Vec<_>>();
+            tiers.push(tier);
+        }
+        metainfo.set_tracker_tiers(tiers);
+        for url in &source.url_list {
+            if !metainfo.url_list.contains(url) {
+                metainfo.url_list.push(url.clone());
+            }
+        }
+        Ok(metainfo)
+    }
+
+    /// Serialize as a `magnet:?` URI. Every value is percent-encoded, so parsing the result
+    /// gives back an equal `MagnetLink`.
+    pub fn to_uri(&self) -> String {
+        let mut params = Vec::new();
+        for topic in &self.exact_topics {
+            let value = match topic {
+                ExactTopic::Btih(hash) => format!("urn:btih:{}", hex::encode(hash)),
+                ExactTopic::Btmh(hash) => format!("urn:btmh:{}", url_encode(hash.as_bytes())),
+                ExactTopic::Other(urn) => url_encode(urn.as_bytes()),
+            };
+            params.push(format!("xt={}", value));
+        }
+        if let Some(name) = &self.display_name {
+            params.push(format!("dn={}", url_encode(name.as_bytes())));
+        }
+        if let Some(length) = self.length {
+            params.push(format!("xl={}", length));
+        }
+        let urls = [
+            ("tr", &self.trackers),
+            ("ws", &self.web_seeds),
+            ("as", &self.acceptable_sources),
+            ("xs", &self.exact_sources),
+        ];
+        for (key, urls) in urls {
+            for url in urls {
+                params.push(format!("{}={}", key, url_encode(url.as_str().as_bytes())));
+            }
+        }
+        for peer in &self.peers {
+            let peer = format!("{}:{}", peer.host, peer.port);
+            params.push(format!("x.pe={}", url_encode(peer.as_bytes())));
+        }
+        if let Some(indices) = &self.select_only {
+            params.push(format!("so={}", format_select_only(indices)));
+        }
+
+        let mut other = self.other_params.iter().collect::<Vec<_>>();
+        other.sort();
+        for (key, values) in other {
+            for value in values {
+                params.push(format!(
+                    "{}={}",
+                    url_encode(key.as_bytes()),
+                    url_encode(value.as_bytes())
+                ));
+            }
+        }
+
+        format!("magnet:?{}", params.join("&"))
+    }
+
     /// Parse a magnet URI into a `MagnetLink`.
     pub fn parse(input: &str) -> anyhow::Result<MagnetLink> {
         let url = Url::parse(input).context("invalid URL")?;
@@ -98,7 +300,7 @@ impl MagnetLink {
             .collect::<anyhow::Result<Vec<_>>>()?;
 
         // ----- dn (optional, we just take the last one if several) -----
-        let display_name = params.get("dn").and_then(|list| list.last().cloned());
+        let display_name = params.remove("dn").and_then(|mut list| list.pop());
 
         // ----- trackers (tr) -----
         let trackers =
@@ -169,7 +371,7 @@ fn parse_exact_topic(xt: &str) -> anyhow::Result<ExactTopic> {
     const BTMH_PREFIX: &str = "urn:btmh:";
 
     if let Some(rest) = xt.strip_prefix(BTIH_PREFIX) {
-        Ok(ExactTopic::Btih(rest.to_string()))
+        Ok(ExactTopic::Btih(parse_btih(rest)?))
     } else if let Some(rest) = xt.strip_prefix(BTMH_PREFIX) {
         Ok(ExactTopic::Btmh(rest.to_string()))
     } else {
@@ -178,6 +380,18 @@ fn parse_exact_topic(xt: &str) -> anyhow::Result<ExactTopic> {
     }
 }
 
+/// Decode a v1 info hash from its hex (40 characters) or base32 (32 characters) form.
+fn parse_btih(value: &str) -> anyhow::Result<[u8; 20]> {
+    let hash = match value.len() {
+        40 => hex::decode(value).context("invalid btih hex")?,
+        32 => BASE32
+            .decode(value.to_ascii_uppercase().as_bytes())
+            .context("invalid btih base32")?,
+        len => bail!("btih must be 40 hex or 32 base32 characters, got {len}"),
+    };
+    Ok(hash.try_into().expect("both encodings decode to 20 bytes"))
+}
+
 /// Collect a list of `String` values into `Url`s.
 /// If `values` is `None`, returns an empty vec.
 fn collect_urls(values: Option<Vec<String>>) -> anyhow::Result<Vec<Url>> {
@@ -243,6 +457,27 @@ fn parse_select_only(value: &str) -> anyhow::Result<Vec<u32>> {
     Ok(result)
 }
 
+/// Format file indices for `so`, collapsing runs into ranges: [0,2,3,4] → `0,2-4`.
+fn format_select_only(indices: &[u32]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut index = 0;
+    while index < indices.len() {
+        let start = indices[index];
+        let mut end = start;
+        while indices.get(index + 1) == Some(&(end + 1)) {
+            end += 1;
+            index += 1;
+        }
+        parts.push(if start == end {
+            start.to_string()
+        } else {
+            format!("{}-{}", start, end)
+        });
+        index += 1;
+    }
+    parts.join(",")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -258,10 +493,7 @@ mod tests {
     fn parse_magnet_link_with_unknown_xt() {
         let magnet_link = "magnet:?xt=urn:invalid:abcdef1234567890abcdef1234567890abcdef12&dn=example_file.txt&tr=http%3A%2F%2Ftracker.example.com%2Fannounce";
         let magnet = MagnetLink::parse(magnet_link).unwrap();
-        assert!(match &magnet.exact_topics[0] {
-            ExactTopic::Other(_) => true,
-            _ => false,
-        });
+        assert!(matches!(&magnet.exact_topics[0], ExactTopic::Other(_)));
     }
 
     #[test]
@@ -277,5 +509,135 @@ mod tests {
         let magnet_link = "magnet:?xt=urn:btih:abcdef1234567890abcdef1234567890abcdef12&xt=urn:btih:1234567890abcdef1234567890abcdef12345678&dn=example_file.txt&tr=http%3A%2F%2Ftracker.example.com%2Fannounce";
         let result = MagnetLink::parse(magnet_link).unwrap();
         assert_eq!(result.exact_topics.len(), 2);
+        assert_eq!(result.swarm_hashes().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn parses_btih_in_hex_and_base32() {
+        let hex = "magnet:?xt=urn:btih:d69f91e6b2ae4c542468d1073a71d4ea13879a7f";
+        let base32 = "magnet:?xt=urn:btih:22pzdzvsvzgfijdi2eDTU4OU5ijypgt7";
+        let expected = ExactTopic::Btih(
+            hex::decode("d69f91e6b2ae4c542468d1073a71d4ea13879a7f")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(MagnetLink::parse(hex).unwrap().exact_topics[0], expected);
+        assert_eq!(MagnetLink::parse(base32).unwrap().exact_topics[0], expected);
+
+        assert!(MagnetLink::parse("magnet:?xt=urn:btih:abcd").is_err());
+        assert!(MagnetLink::parse("magnet:?xt=urn:btih:0123456789012345678901234567890!").is_err());
+    }
+
+    #[test]
+    fn decodes_btih_and_btmh_info_hashes() {
+        let v1 = "abcdef1234567890abcdef1234567890abcdef12";
+        let v2 = "0123456789abcdef".repeat(4);
+        let hybrid = format!("magnet:?xt=urn:btih:{v1}&xt=urn:btmh:1220{v2}");
+        let magnet = MagnetLink::parse(&hybrid).unwrap();
+        assert_eq!(
+            magnet.info_hashes().unwrap(),
+            vec![hex::decode(v1).unwrap(), hex::decode(&v2).unwrap()]
+        );
+        assert_eq!(
+            magnet.swarm_hashes().unwrap(),
+            vec![hex::decode(v1).unwrap(), hex::decode(&v2[..40]).unwrap()]
+        );
+
+        let v2_only = MagnetLink::parse(&format!("magnet:?xt=urn:btmh:1220{v2}")).unwrap();
+        assert_eq!(
+            v2_only.swarm_hashes().unwrap(),
+            vec![hex::decode(&v2[..40]).unwrap()]
+        );
+
+        let not_sha256 = MagnetLink::parse(&format!("magnet:?xt=urn:btmh:1120{v2}")).unwrap();
+        assert!(not_sha256.info_hashes().is_err());
+    }
+
+    #[test]
+    fn to_uri_round_trips() {
+        let link = "magnet:?xt=urn:btih:abcdef1234567890abcdef1234567890abcdef12&dn=a%20b%2Bc%26d.txt&xl=1234&tr=http%3A%2F%2Ft.example%2Fannounce%3Fkey%3D1&tr=udp%3A%2F%2Fu.example%3A80&ws=http%3A%2F%2Fw.example%2Ff&x.pe=%5B2001%3Adb8%3A%3A1%5D%3A51413&so=0,2-4,7&foo=bar";
+        let magnet = MagnetLink::parse(link).unwrap();
+        assert_eq!(magnet.display_name.as_deref(), Some("a b+c&d.txt"));
+
+        let uri = magnet.to_uri();
+        assert!(uri.starts_with("magnet:?xt=urn:btih:abcdef"));
+        assert!(uri.contains("&so=0,2-4,7"));
+        assert_eq!(MagnetLink::parse(&uri).unwrap(), magnet);
+        assert_eq!(MagnetLink::parse(&uri).unwrap().to_uri(), uri);
+    }
+
+    #[test]
+    fn builds_magnet_from_torrent() {
+        let torrent = std::fs::read("sample.torrent").unwrap();
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        let magnet = MagnetLink::from_metainfo(&metainfo).unwrap();
+
+        assert_eq!(
+            magnet.to_uri(),
+            "magnet:?xt=urn:btih:d69f91e6b2ae4c542468d1073a71d4ea13879a7f&dn=sample.txt&xl=92063\
+&tr=http%3A%2F%2Fbittorrent-test-tracker.codecrafters.io%2Fannounce"
+        );
+        assert!(magnet.check_length(&metainfo).is_ok());
+
+        let wrong = MagnetLink {
+            length: Some(92064),
+            ..magnet
+        };
+        assert!(wrong.check_length(&metainfo).is_err());
+    }
+
+    #[test]
+    fn rebuilds_torrent_from_info_bytes() {
+        let torrent = std::fs::read("sample.torrent").unwrap();
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        let mut magnet = MagnetLink::from_metainfo(&metainfo).unwrap();
+        magnet
+            .trackers
+            .push(Url::parse("udp://tracker.example:6969").unwrap());
+        magnet
+            .web_seeds
+            .push(Url::parse("http://seed.example/").unwrap());
+
+        let rebuilt = magnet.to_metainfo(metainfo.info_bytes()).unwrap();
+        assert_eq!(rebuilt.info_bytes(), metainfo.info_bytes());
+        assert_eq!(rebuilt.info_hash, metainfo.info_hash);
+        assert_eq!(rebuilt.announce, metainfo.announce);
+        assert_eq!(rebuilt.tracker_tiers()[0].len(), 2);
+        assert_eq!(rebuilt.url_list, vec!["http://seed.example/"]);
+
+        assert!(other_hash_link()
+            .to_metainfo(metainfo.info_bytes())
+            .is_err());
+    }
+
+    #[test]
+    fn merges_exact_source_with_link_trackers() {
+        let torrent = std::fs::read("sample.torrent").unwrap();
+        let mut source = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        source.url_list = vec!["http://seed.example/".to_string()];
+        let mut magnet = MagnetLink::from_metainfo(&source).unwrap();
+        magnet.trackers = vec![Url::parse("udp://tracker.example:6969").unwrap()];
+        magnet.web_seeds = vec![Url::parse("http://link-seed.example/").unwrap()];
+
+        let merged = magnet.merge_metainfo(&source).unwrap();
+        assert_eq!(merged.info_bytes(), source.info_bytes());
+        assert_eq!(
+            merged.tracker_tiers(),
+            vec![
+                vec!["udp://tracker.example:6969".to_string()],
+                vec![source.announce.clone()],
+            ]
+        );
+        assert_eq!(
+            merged.url_list,
+            vec!["http://link-seed.example/", "http://seed.example/"]
+        );
+
+        assert!(other_hash_link().merge_metainfo(&source).is_err());
+    }
+
+    fn other_hash_link() -> MagnetLink {
+        MagnetLink::parse("magnet:?xt=urn:btih:abcdef1234567890abcdef1234567890abcdef12").unwrap()
     }
 }
@@ -0,0 +1,106 @@
+//! SHA-256 merkle trees used by BitTorrent v2 (BEP-52).
+//!
+//! A file is split into 16 KiB blocks whose SHA-256 hashes are the leaves of a binary tree,
+//! padded with zero hashes up to a power of two; each inner node hashes the concatenation of
+//! its two children. The root is the file's `pieces root`, and the layer whose nodes each
+//! cover one piece is the file's entry in `piece layers`.
+
+use crate::utils;
+
+pub const BLOCK_SIZE: u64 = 16 * 1024;
+
+pub type Hash256 = [u8; 32];
+
+/// SHA-256 of each 16 KiB block of `data`; the last block may be shorter.
+pub fn block_hashes(data: &[u8]) -> Vec<Hash256> {
+    data.chunks(BLOCK_SIZE as usize).map(utils::sha256).collect()
+}
+
+fn hash_pair(left: &Hash256, right: &Hash256) -> Hash256 {
+    let mut buffer = [0u8; 64];
+    buffer[..32].copy_from_slice(left);
+    buffer[32..].copy_from_slice(right);
+    utils::sha256(&buffer)
+}
+
+/// Root of the tree over `layer`, padded with `pad` up to `width` nodes (a power of two).
+fn root(mut layer: Vec<Hash256>, width: usize, pad: Hash256) -> Hash256 {
+    debug_assert!(width.is_power_of_two() && width >= layer.len());
+    layer.resize(width, pad);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    layer[0]
+}
+
+/// Root of a subtree of `leaves` zero leaves, i.e. the padding node at that height.
+fn zero_root(leaves: u64) -> Hash256 {
+    let mut hash = [0u8; 32];
+    let mut width = leaves;
+    while width > 1 {
+        hash = hash_pair(&hash, &hash);
+        width /= 2;
+    }
+    hash
+}
+
+/// `pieces root` of a whole file.
+pub fn file_root(data: &[u8]) -> Hash256 {
+    let leaves = block_hashes(data);
+    let width = leaves.len().next_power_of_two();
+    root(leaves, width, [0; 32])
+}
+
+/// Piece-layer hash of one piece; `data` is shorter than `piece_length` only for the last
+/// piece of a file.
+pub fn piece_root(data: &[u8], piece_length: u64) -> Hash256 {
+    root(
+        block_hashes(data),
+        (piece_length / BLOCK_SIZE) as usize,
+        [0; 32],
+    )
+}
+
+/// `pieces root` implied by a file's piece layer.
+pub fn layer_root(layer: &[Hash256], piece_length: u64) -> Hash256 {
+    root(
+        layer.to_vec(),
+        layer.len().next_power_of_two(),
+        zero_root(piece_length / BLOCK_SIZE),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_file_root_pads_to_a_power_of_two_of_blocks() {
+        let data = vec![7u8; BLOCK_SIZE as usize * 2 + 100];
+        let blocks = block_hashes(&data);
+        let expected = hash_pair(
+            &hash_pair(&blocks[0], &blocks[1]),
+            &hash_pair(&blocks[2], &[0; 32]),
+        );
+        assert_eq!(file_root(&data), expected);
+        assert_eq!(file_root(b"abc"), utils::sha256(b"abc"));
+    }
+
+    #[test]
+    fn piece_layer_reproduces_the_file_root() {
+        let piece_length = BLOCK_SIZE * 4;
+        let data = (0..piece_length * 2 + BLOCK_SIZE + 5)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let layer = data
+            .chunks(piece_length as usize)
+            .map(|piece| piece_root(piece, piece_length))
+            .collect::<Vec<_>>();
+        assert_eq!(layer.len(), 3);
+        assert_eq!(layer_root(&layer, piece_length), file_root(&data));
+    }
+}
@@ -1,15 +1,65 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use super::merkle::{self, Hash256};
+use super::TorrentFile;
+use crate::bencode::{self, BencodeRef, BencodeValue};
 use crate::utils;
-use serde::{Deserialize, Serialize};
+use anyhow::{bail, Context};
+use serde::Deserialize;
 use serde_bytes;
 
+/// Which BitTorrent protocol version(s) the info dictionary describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaVersion {
+    /// BEP-3: SHA-1 `pieces` over the concatenated files.
+    V1,
+    /// BEP-52: a `file tree` with a SHA-256 merkle tree per file.
+    V2,
+    /// Both of the above describing the same data, so the torrent joins both swarms.
+    Hybrid,
+}
+
 pub struct TorrentMetainfo {
     pub announce: String,
+    /// Tracker tiers from `announce-list` (BEP-12), in file order.
+    pub announce_list: Vec<Vec<String>>,
     // From "info" dictionary
+    pub name: String,
+    pub version: MetaVersion,
+    /// BEP-27 `private` flag: peers may only come from the torrent's own trackers.
+    pub private: bool,
+    /// `source` tag, set by private trackers to give each site's torrents a distinct hash.
+    pub source: Option<String>,
     pub piece_length: u64,
+    /// Concatenated SHA-1 piece hashes (v1 and hybrid).
     pub pieces: Vec<u8>,
+    /// Total length of the files, not counting padding files or the gaps that align v2
+    /// files to pieces.
     pub length: u64,
+    files: Vec<TorrentFile>,
+    multi_file: bool,
+    /// v2 `piece layers`, keyed by the pieces root of the file they belong to.
+    piece_layers: BTreeMap<Hash256, Vec<Hash256>>,
+    // Outside the info dictionary, so not covered by the info hash
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    /// Creation time in seconds since the Unix epoch.
+    pub creation_date: Option<i64>,
+    /// Character encoding of the strings in the file, as declared by older clients.
+    pub encoding: Option<String>,
+    /// Web seeds (BEP-19 `url-list`).
+    pub url_list: Vec<String>,
+    /// Top-level keys not interpreted above (e.g. DHT `nodes`), written back as they were.
+    other_keys: BTreeMap<Vec<u8>, BencodeValue>,
     // Metadata
+    /// Hash identifying the swarm: the SHA-1 of the info dictionary for v1 and hybrid
+    /// torrents, the SHA-256 truncated to 20 bytes for v2 torrents.
     pub info_hash: Vec<u8>,
+    /// Full SHA-256 of the info dictionary (v2 and hybrid).
+    pub info_hash_v2: Option<Hash256>,
+    /// The bencoded info dictionary exactly as received; the info hashes are taken over it.
+    info_bytes: Vec<u8>,
 }
 
 impl TorrentMetainfo {
@@ -20,77 +70,498 @@ impl TorrentMetainfo {
     }
 
     pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_bytes_limited(bytes, bencode::DecodeLimits::unlimited())
+    }
+
+    /// Like [`from_bytes`](Self::from_bytes), enforcing `limits` on a .torrent file from an
+    /// untrusted source.
+    pub fn from_bytes_limited(bytes: &[u8], limits: bencode::DecodeLimits) -> anyhow::Result<Self> {
+        let root =
+            bencode::parse_ref_limited(bytes, limits).context("Failed to decode torrent file")?;
         let parsed_metainfo: TorrentMetainfoSerde =
-            serde_bencode::from_bytes(&bytes).expect("Failed to decode torrent file");
-
-        let info_hash_bytes = serde_bencode::to_bytes(&parsed_metainfo.info)
-            .expect("Failed to re-encode info dictionary for hashing");
-
-        let length = parsed_metainfo
-            .info
-            .length
-            .or_else(|| {
-                parsed_metainfo
-                    .info
-                    .files
-                    .as_ref()
-                    .map(|files| files.iter().map(|f| f.length).sum())
-            })
-            .unwrap_or(0);
+            bencode::from_ref(&root).context("Invalid torrent file")?;
 
-        Ok(TorrentMetainfo {
-            announce: parsed_metainfo.announce,
-            piece_length: parsed_metainfo.info.piece_length,
-            pieces: parsed_metainfo.info.pieces,
-            length,
-            info_hash: utils::sha1(&info_hash_bytes),
-        })
+        // Keep the info dictionary exactly as it appears in the file; re-encoding the parsed
+        // struct would drop any keys it does not know about and change the info hash.
+        let info_bytes = root
+            .get("info")
+            .context("Torrent file has no info dictionary")?
+            .raw();
+
+        let mut metainfo = Self::from_info(
+            parsed_metainfo.announce,
+            parsed_metainfo.info,
+            info_bytes.to_vec(),
+            limits,
+        )?;
+        metainfo.announce_list = root
+            .get("announce-list")
+            .map(announce_tiers)
+            .unwrap_or_default();
+        metainfo.comment = text(&root, "comment");
+        metainfo.created_by = text(&root, "created by");
+        metainfo.creation_date = root.get("creation date").and_then(BencodeRef::as_int);
+        metainfo.encoding = text(&root, "encoding");
+        // `url-list` may be a single URL or a list of them.
+        let url_list = root.get("url-list");
+        metainfo.url_list = match url_list.and_then(BencodeRef::as_list) {
+            Some(urls) => urls
+                .iter()
+                .filter_map(BencodeRef::as_str)
+                .map(str::to_string)
+                .collect(),
+            None => url_list
+                .and_then(BencodeRef::as_str)
+                .map(str::to_string)
+                .into_iter()
+                .collect(),
+        };
+        if let Some(layers) = root.get("piece layers") {
+            metainfo.set_piece_layers(layers)?;
+        }
+        metainfo.other_keys = root
+            .as_dict()
+            .unwrap_or_default()
+            .iter()
+            .filter(|(key, _)| !KNOWN_ROOT_KEYS.contains(key))
+            .map(|(key, value)| (key.to_vec(), value.to_value()))
+            .collect();
+        Ok(metainfo)
     }
 
     pub fn from_info_bytes(announce: String, info_bytes: &[u8]) -> anyhow::Result<Self> {
-        let info_dict: InfoDictionary =
-            serde_bencode::from_bytes(info_bytes).expect("Failed to decode info dictionary");
-
-        let length = info_dict
-            .length
-            .or_else(|| {
-                info_dict
-                    .files
-                    .as_ref()
-                    .map(|files| files.iter().map(|f| f.length).sum())
-            })
-            .unwrap_or(0);
+        // Info dictionaries passed here come from peers (ut_metadata), so decode them with limits.
+        let limits = bencode::DecodeLimits::untrusted();
+        let info_dict: InfoDictionary = bencode::from_bytes_limited(info_bytes, limits)
+            .context("Failed to decode info dictionary")?;
+
+        Self::from_info(announce, info_dict, info_bytes.to_vec(), limits)
+    }
+
+    fn from_info(
+        announce: String,
+        info: InfoDictionary,
+        info_bytes: Vec<u8>,
+        limits: bencode::DecodeLimits,
+    ) -> anyhow::Result<Self> {
+        let name = info.name.context("Torrent info dictionary has no name")?;
+
+        let tree_entries = match info.meta_version {
+            Some(2) => {
+                if !info.piece_length.is_power_of_two() || info.piece_length < merkle::BLOCK_SIZE {
+                    bail!("v2 piece length must be a power of two of at least 16 KiB");
+                }
+                let tree = bencode::parse_ref_limited(&info_bytes, limits)?;
+                let tree = tree
+                    .get("file tree")
+                    .context("v2 info dictionary has no file tree")?;
+                let mut entries = Vec::new();
+                parse_file_tree(tree, &mut Vec::new(), &mut entries)?;
+                Some(entries)
+            }
+            None | Some(1) => None,
+            Some(other) => bail!("Unsupported meta version {}", other),
+        };
+
+        // The v1 file list; hybrid torrents describe the same files in both forms, with BEP-47
+        // padding files in the v1 list keeping every file aligned to a piece boundary.
+        let v1_files = match (&info.pieces, info.files) {
+            (None, _) => None,
+            // Multi-file torrents: paths are relative to a directory called `name`.
+            (Some(_), Some(entries)) => {
+                let extras = entries
+                    .iter()
+                    .map(|f| (f.is_padding(), f.md5sum.clone()))
+                    .collect::<Vec<_>>();
+                let mut files =
+                    TorrentFile::layout(entries.into_iter().map(|f| (f.path, f.length)))?;
+                for (file, (padding, md5sum)) in files.iter_mut().zip(extras) {
+                    file.padding = padding;
+                    file.md5sum = md5sum;
+                }
+                Some((files, true))
+            }
+            (Some(_), None) => {
+                let length = info
+                    .length
+                    .context("Torrent info dictionary has neither length nor files")?;
+                let mut files = TorrentFile::layout([(vec![name.clone()], length)])?;
+                files[0].md5sum = info.md5sum;
+                Some((files, false))
+            }
+        };
+
+        let (version, files, multi_file) = match (v1_files, tree_entries) {
+            (Some((files, multi_file)), None) => (MetaVersion::V1, files, multi_file),
+            (None, Some(entries)) => {
+                let files = TorrentFile::layout_aligned(entries, info.piece_length)?;
+                // A tree holding a single top-level file is stored like a v1 single-file torrent.
+                let multi_file = !(files.len() == 1 && files[0].path.len() == 1);
+                (MetaVersion::V2, files, multi_file)
+            }
+            (Some((mut files, multi_file)), Some(entries)) => {
+                attach_pieces_roots(&mut files, entries, info.piece_length)?;
+                (MetaVersion::Hybrid, files, multi_file)
+            }
+            (None, None) => bail!("Torrent info dictionary has no pieces"),
+        };
+        if multi_file {
+            super::files::check_path_component(&name)?;
+        }
+        let length = files
+            .iter()
+            .filter(|file| !file.padding)
+            .map(|file| file.length)
+            .sum();
+
+        // Catch inconsistent piece hashes here rather than as out-of-range piece indexes
+        // during a download.
+        if let Some(pieces) = &info.pieces {
+            if info.piece_length == 0 {
+                bail!("Torrent piece length is zero");
+            }
+            if pieces.len() % 20 != 0 {
+                bail!(
+                    "Torrent pieces length {} is not a multiple of 20",
+                    pieces.len()
+                );
+            }
+            let data_end = files.iter().map(TorrentFile::end).max().unwrap_or(0);
+            let expected = data_end.div_ceil(info.piece_length);
+            if pieces.len() as u64 / 20 != expected {
+                bail!(
+                    "Torrent has {} piece hashes but {} bytes in pieces of {} need {}",
+                    pieces.len() / 20,
+                    data_end,
+                    info.piece_length,
+                    expected
+                );
+            }
+        }
+
+        let (info_hash, info_hash_v2) = match version {
+            MetaVersion::V1 => (utils::sha1(&info_bytes), None),
+            MetaVersion::V2 => {
+                let hash = utils::sha256(&info_bytes);
+                (hash[..20].to_vec(), Some(hash))
+            }
+            MetaVersion::Hybrid => (utils::sha1(&info_bytes), Some(utils::sha256(&info_bytes))),
+        };
 
         Ok(TorrentMetainfo {
             announce,
-            piece_length: info_dict.piece_length,
-            pieces: info_dict.pieces,
+            announce_list: Vec::new(),
+            name,
+            version,
+            private: info.private == Some(1),
+            source: info.source,
+            piece_length: info.piece_length,
+            pieces: info.pieces.unwrap_or_default(),
             length,
-            info_hash: utils::sha1(info_bytes),
+            files,
+            multi_file,
+            piece_layers: BTreeMap::new(),
+            comment: None,
+            created_by: None,
+            creation_date: None,
+            encoding: None,
+            url_list: Vec::new(),
+            other_keys: BTreeMap::new(),
+            info_hash,
+            info_hash_v2,
+            info_bytes,
         })
     }
 
+    /// Load the `piece layers` dictionary, checking every layer against its file's root.
+    fn set_piece_layers(&mut self, layers: &BencodeRef) -> anyhow::Result<()> {
+        let entries = layers
+            .as_dict()
+            .context("piece layers must be a dictionary")?;
+        for (root, layer) in entries {
+            let root = Hash256::try_from(*root).context("piece layers key must be 32 bytes")?;
+            let layer = layer
+                .as_bytes()
+                .filter(|bytes| bytes.len() % 32 == 0)
+                .context("piece layer must be a multiple of 32 bytes")?
+                .chunks(32)
+                .map(|hash| Hash256::try_from(hash).expect("chunk is 32 bytes"))
+                .collect::<Vec<_>>();
+
+            let file = self
+                .files
+                .iter()
+                .find(|file| file.pieces_root == Some(root))
+                .context("piece layer does not belong to any file")?;
+            if layer.len() as u64 != file.length.div_ceil(self.piece_length)
+                || merkle::layer_root(&layer, self.piece_length) != root
+            {
+                bail!(
+                    "piece layer of {} does not match its pieces root",
+                    file.path.join("/")
+                );
+            }
+            self.piece_layers.insert(root, layer);
+        }
+        Ok(())
+    }
+
+    /// Encode as a .torrent file. The info dictionary is written back byte for byte, so the
+    /// result always has the same info hash.
     pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
-        let metainfo_serde = TorrentMetainfoSerde {
-            announce: self.announce.clone(),
-            info: InfoDictionary {
-                piece_length: self.piece_length,
-                pieces: self.pieces.clone(),
-                length: Some(self.length),
-                files: None,
-                name: None,
-            },
+        let mut root = self.other_keys.clone();
+        if !self.announce.is_empty() {
+            root.insert(
+                b"announce".to_vec(),
+                BencodeValue::from(self.announce.as_str()),
+            );
+        }
+        if !self.announce_list.is_empty() {
+            let tiers = self
+                .announce_list
+                .iter()
+                .map(|tier| {
+                    BencodeValue::List(tier.iter().map(|url| url.as_str().into()).collect())
+                })
+                .collect::<Vec<_>>();
+            root.insert(b"announce-list".to_vec(), BencodeValue::from(tiers));
+        }
+        let optional_text = [
+            (&b"comment"[..], &self.comment),
+            (b"created by", &self.created_by),
+            (b"encoding", &self.encoding),
+        ];
+        for (key, value) in optional_text {
+            if let Some(value) = value {
+                root.insert(key.to_vec(), BencodeValue::from(value.as_str()));
+            }
+        }
+        if let Some(date) = self.creation_date {
+            root.insert(b"creation date".to_vec(), BencodeValue::from(date));
+        }
+        match self.url_list.as_slice() {
+            [] => {}
+            [url] => {
+                root.insert(b"url-list".to_vec(), BencodeValue::from(url.as_str()));
+            }
+            urls => {
+                let urls = urls.iter().map(|url| url.as_str().into()).collect();
+                root.insert(b"url-list".to_vec(), BencodeValue::List(urls));
+            }
+        }
+        if !self.piece_layers.is_empty() {
+            let layers = self
+                .piece_layers
+                .iter()
+                .map(|(root, layer)| (root.to_vec(), BencodeValue::from(layer.concat())))
+                .collect::<BTreeMap<_, _>>();
+            root.insert(b"piece layers".to_vec(), BencodeValue::from(layers));
+        }
+        Ok(encode_with_info(&root, &self.info_bytes))
+    }
+
+    /// Tracker tiers to announce to: `announce-list` when present (BEP-12 says to ignore
+    /// `announce` then), otherwise a single tier holding `announce`.
+    pub fn tracker_tiers(&self) -> Vec<Vec<String>> {
+        if self.announce_list.iter().any(|tier| !tier.is_empty()) {
+            self.announce_list.clone()
+        } else if self.announce.is_empty() {
+            Vec::new()
+        } else {
+            vec![vec![self.announce.clone()]]
+        }
+    }
+
+    /// Replace the trackers. The first tracker of the first tier becomes `announce`; the
+    /// tiers are kept as `announce-list` when there is more than one tracker.
+    pub fn set_tracker_tiers(&mut self, tiers: Vec<Vec<String>>) {
+        let tiers = tiers
+            .into_iter()
+            .filter(|tier| !tier.is_empty())
+            .collect::<Vec<_>>();
+        self.announce = tiers
+            .first()
+            .map(|tier| tier[0].clone())
+            .unwrap_or_default();
+        self.announce_list = if tiers.iter().map(Vec::len).sum::<usize>() > 1 {
+            tiers
+        } else {
+            Vec::new()
         };
+    }
 
-        Ok(serde_bencode::to_bytes(&metainfo_serde)?)
+    /// The bencoded info dictionary as it was parsed.
+    pub fn info_bytes(&self) -> &[u8] {
+        &self.info_bytes
+    }
+
+    /// Files in the order their data appears in the torrent. A single-file torrent has one
+    /// entry named after the torrent.
+    pub fn files(&self) -> &[TorrentFile] {
+        &self.files
+    }
+
+    /// Whether the torrent uses the multi-file layout, i.e. its files live in a directory
+    /// named after the torrent rather than being the torrent itself.
+    pub fn is_multi_file(&self) -> bool {
+        self.multi_file
     }
 
     pub fn get_info_hash_hex(&self) -> String {
         hex::encode(&self.info_hash)
     }
 
+    /// The 20-byte hashes to announce and handshake under: the SHA-1 and/or the truncated
+    /// SHA-256. A hybrid torrent is in both swarms.
+    pub fn swarm_hashes(&self) -> Vec<Vec<u8>> {
+        let mut hashes = vec![self.info_hash.clone()];
+        if let Some(v2) = self.info_hash_v2 {
+            if v2[..20] != self.info_hash[..] {
+                hashes.push(v2[..20].to_vec());
+            }
+        }
+        hashes
+    }
+
+    /// Whether `hash` identifies this torrent: the v1 SHA-1, or the v2 SHA-256 in full or
+    /// truncated to 20 bytes.
+    pub fn has_info_hash(&self, hash: &[u8]) -> bool {
+        hash == self.info_hash
+            || self
+                .info_hash_v2
+                .is_some_and(|v2| hash == v2 || hash == &v2[..20])
+    }
+
     pub fn get_piece_count(&self) -> usize {
-        self.pieces.len() / 20
+        match self.version {
+            MetaVersion::V1 | MetaVersion::Hybrid => self.pieces.len() / 20,
+            MetaVersion::V2 => self.data_end().div_ceil(self.piece_length) as usize,
+        }
+    }
+
+    /// Length of piece `index`; only the last piece (of the torrent, or of a file in v2) is
+    /// shorter than `piece_length`.
+    pub fn piece_len(&self, index: u32) -> u64 {
+        let start = index as u64 * self.piece_length;
+        let end = match self.version {
+            MetaVersion::V1 | MetaVersion::Hybrid => self.data_end(),
+            MetaVersion::V2 => self
+                .file_at(start)
+                .map(TorrentFile::end)
+                .unwrap_or(self.data_end()),
+        };
+        end.saturating_sub(start).min(self.piece_length)
+    }
+
+    /// Check downloaded piece data against the SHA-1 piece hash (v1), the file's merkle
+    /// tree (v2), or both (hybrid).
+    pub fn verify_piece(&self, index: u32, data: &[u8]) -> anyhow::Result<()> {
+        let valid = match self.version {
+            MetaVersion::V1 => self.verify_v1_piece(index, data)?,
+            MetaVersion::V2 => self.verify_v2_piece(index, data)?,
+            MetaVersion::Hybrid => {
+                self.verify_v1_piece(index, data)? && self.verify_v2_piece(index, data)?
+            }
+        };
+        if !valid {
+            bail!("hash mismatch for piece {}", index);
+        }
+        Ok(())
+    }
+
+    fn verify_v1_piece(&self, index: u32, data: &[u8]) -> anyhow::Result<bool> {
+        let start = index as usize * 20;
+        let expected = self
+            .pieces
+            .get(start..start + 20)
+            .with_context(|| format!("no hash for piece {}", index))?;
+        Ok(utils::sha1(data) == expected)
+    }
+
+    fn verify_v2_piece(&self, index: u32, data: &[u8]) -> anyhow::Result<bool> {
+        let start = index as u64 * self.piece_length;
+        let file = self
+            .file_at(start)
+            .with_context(|| format!("piece {} is not part of any file", index))?;
+        let root = file.pieces_root.context("file has no pieces root")?;
+        // In a hybrid torrent the piece continues into the padding file after `file`.
+        let data = &data[..data.len().min((file.end() - start) as usize)];
+        if file.length <= self.piece_length {
+            return Ok(merkle::file_root(data) == root);
+        }
+        let layer = self
+            .piece_layers
+            .get(&root)
+            .with_context(|| format!("missing piece layer for {}", file.path.join("/")))?;
+        let local = ((start - file.offset) / self.piece_length) as usize;
+        Ok(layer.get(local) == Some(&merkle::piece_root(data, self.piece_length)))
+    }
+
+    /// Indexes of the pieces holding data of `file`; empty for an empty file.
+    pub fn piece_range(&self, file: &TorrentFile) -> Range<u32> {
+        let first = file.offset / self.piece_length;
+        if file.length == 0 {
+            return first as u32..first as u32;
+        }
+        first as u32..file.end().div_ceil(self.piece_length) as u32
+    }
+
+    /// Indexes of the pieces overlapping the files at `indices` (positions in [`files`]),
+    /// sorted and without duplicates. Used for BEP-53 file selection.
+    ///
+    /// [`files`]: Self::files
+    pub fn pieces_for_files(&self, indices: &[u32]) -> anyhow::Result<Vec<u32>> {
+        let mut pieces = BTreeSet::new();
+        for &index in indices {
+            let file = self.files.get(index as usize).with_context(|| {
+                format!(
+                    "file index {} out of range, torrent has {} files",
+                    index,
+                    self.files.len()
+                )
+            })?;
+            pieces.extend(self.piece_range(file));
+        }
+        Ok(pieces.into_iter().collect())
+    }
+
+    /// Fail unless every v2 file overlapping `pieces` can be verified. Files longer than a
+    /// piece need their layer from `piece layers`, which is not part of the info dictionary,
+    /// so metadata fetched from peers (magnet links) lacks it.
+    pub fn check_piece_layers(&self, pieces: &[u32]) -> anyhow::Result<()> {
+        if self.version == MetaVersion::V1 {
+            return Ok(());
+        }
+        for file in &self.files {
+            let Some(root) = file.pieces_root else {
+                continue;
+            };
+            let range = self.piece_range(file);
+            if file.length > self.piece_length
+                && !self.piece_layers.contains_key(&root)
+                && pieces.iter().any(|piece| range.contains(piece))
+            {
+                bail!(
+                    "piece layers required: no piece layer for {}, which is larger than a piece \
+                     (use the .torrent file, magnet metadata does not include piece layers)",
+                    file.path.join("/")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// End of the torrent data as laid out in pieces, including padding files and the gaps
+    /// that align v2 files to pieces.
+    fn data_end(&self) -> u64 {
+        self.files.iter().map(TorrentFile::end).max().unwrap_or(0)
+    }
+
+    /// The non-empty, non-padding file containing byte `offset` of the torrent data.
+    fn file_at(&self, offset: u64) -> Option<&TorrentFile> {
+        self.files
+            .iter()
+            .find(|file| !file.padding && file.offset <= offset && offset < file.end())
     }
 
     pub fn get_piece_hashes(&self) -> Vec<String> {
@@ -109,27 +580,511 @@ impl TorrentMetainfo {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct TorrentMetainfoSerde {
+    #[serde(default)]
     announce: String,
     info: InfoDictionary,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct InfoDictionary {
+    #[serde(rename = "meta version")]
+    meta_version: Option<u64>,
     #[serde(rename = "piece length")]
     piece_length: u64,
-    #[serde(with = "serde_bytes")]
-    pieces: Vec<u8>,
+    #[serde(with = "serde_bytes", default)]
+    pieces: Option<Vec<u8>>,
     length: Option<u64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     files: Option<Vec<FileEntry>>, // For multi-file torrents
-    #[serde(skip_serializing_if = "Option::is_none")]
     name: Option<String>,
+    #[serde(default)]
+    private: Option<i64>,
+    source: Option<String>,
+    md5sum: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct FileEntry {
     length: u64,
     path: Vec<String>,
+    #[serde(default)]
+    attr: Option<String>,
+    md5sum: Option<String>,
+}
+
+impl FileEntry {
+    /// BEP-47 padding file, i.e. zeros filling the gap up to the next piece boundary.
+    fn is_padding(&self) -> bool {
+        self.attr.as_deref().is_some_and(|attr| attr.contains('p'))
+    }
+}
+
+/// Take the pieces roots of a hybrid torrent from its `file tree`, checking that the tree
+/// describes the same files, at the same piece-aligned offsets, as the v1 file list.
+fn attach_pieces_roots(
+    files: &mut [TorrentFile],
+    entries: Vec<(Vec<String>, u64, Option<Hash256>)>,
+    piece_length: u64,
+) -> anyhow::Result<()> {
+    let mut entries = entries
+        .into_iter()
+        .map(|(path, length, root)| (path, (length, root)))
+        .collect::<BTreeMap<_, _>>();
+    for file in files.iter_mut().filter(|file| !file.padding) {
+        let (length, root) = entries
+            .remove(&file.path)
+            .with_context(|| format!("{} is missing from the file tree", file.path.join("/")))?;
+        if length != file.length || (length > 0 && file.offset % piece_length != 0) {
+            bail!(
+                "v1 and v2 descriptions of {} do not match",
+                file.path.join("/")
+            );
+        }
+        file.pieces_root = root;
+    }
+    if let Some(path) = entries.keys().next() {
+        bail!("{} is missing from the v1 file list", path.join("/"));
+    }
+    Ok(())
+}
+
+/// Flatten a v2 `file tree` into (path, length, pieces root) entries in tree order. A file
+/// is a dictionary with a single empty key holding its `length` and `pieces root`.
+fn parse_file_tree(
+    node: &BencodeRef,
+    path: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, u64, Option<Hash256>)>,
+) -> anyhow::Result<()> {
+    let entries = node
+        .as_dict()
+        .context("file tree nodes must be dictionaries")?;
+    for (key, child) in entries {
+        if key.is_empty() {
+            let length = child
+                .get("length")
+                .and_then(BencodeRef::as_u64)
+                .context("file tree entry has no length")?;
+            let pieces_root = child
+                .get("pieces root")
+                .map(|root| {
+                    root.as_bytes()
+                        .and_then(|bytes| Hash256::try_from(bytes).ok())
+                        .context("pieces root must be 32 bytes")
+                })
+                .transpose()?;
+            if length > 0 && pieces_root.is_none() {
+                bail!("file tree entry {} has no pieces root", path.join("/"));
+            }
+            out.push((path.clone(), length, pieces_root));
+        } else {
+            let component =
+                String::from_utf8(key.to_vec()).context("file tree path is not UTF-8")?;
+            path.push(component);
+            parse_file_tree(child, path, out)?;
+            path.pop();
+        }
+    }
+    Ok(())
+}
+
+/// Top-level keys `TorrentMetainfo` reads into its own fields and writes back itself.
+const KNOWN_ROOT_KEYS: [&[u8]; 9] = [
+    b"announce",
+    b"announce-list",
+    b"comment",
+    b"created by",
+    b"creation date",
+    b"encoding",
+    b"info",
+    b"piece layers",
+    b"url-list",
+];
+
+/// A UTF-8 string value of `dict`, if present.
+fn text(dict: &BencodeRef, key: &str) -> Option<String> {
+    dict.get(key)
+        .and_then(BencodeRef::as_str)
+        .map(str::to_string)
+}
+
+/// The well-formed tiers of an `announce-list`. Anything that is not a list of URL strings
+/// is dropped rather than failing the whole torrent; with no tiers left, BEP-12 falls back
+/// to `announce`.
+fn announce_tiers(list: &BencodeRef) -> Vec<Vec<String>> {
+    list.as_list()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(BencodeRef::as_list)
+        .map(|tier| {
+            tier.iter()
+                .filter_map(BencodeRef::as_str)
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .filter(|tier| !tier.is_empty())
+        .collect()
+}
+
+/// Encode the top-level dictionary `root` with `info_bytes` spliced in verbatim as its
+/// `info` entry.
+fn encode_with_info(root: &BTreeMap<Vec<u8>, BencodeValue>, info_bytes: &[u8]) -> Vec<u8> {
+    let mut entries: BTreeMap<&[u8], Vec<u8>> = root
+        .iter()
+        .filter(|(key, _)| key.as_slice() != b"info")
+        .map(|(key, value)| (key.as_slice(), value.encode()))
+        .collect();
+    entries.insert(b"info", info_bytes.to_vec());
+
+    let mut out = vec![b'd'];
+    for (key, value) in entries {
+        out.extend_from_slice(&BencodeValue::from(key).encode());
+        out.extend_from_slice(&value);
+    }
+    out.push(b'e');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_hash_covers_unknown_info_keys() {
+        let info = b"d6:lengthi3e4:name3:abc12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaa7:privatei1ee";
+        let mut torrent = b"d8:announce3:url4:info".to_vec();
+        torrent.extend_from_slice(info);
+        torrent.push(b'e');
+
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        assert_eq!(metainfo.info_hash, utils::sha1(info));
+        assert!(metainfo.private);
+        assert_eq!(metainfo.length, 3);
+        assert!(!metainfo.is_multi_file());
+        assert_eq!(metainfo.files()[0].path, vec!["abc".to_string()]);
+    }
+
+    #[test]
+    fn to_bytes_writes_info_dictionary_back_unchanged() {
+        // Unsorted keys and an unknown `source` key: re-encoding would change both.
+        let info = b"d4:name3:abc6:lengthi3e12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaa6:source3:xyze";
+        let mut torrent = b"d8:announce3:url4:info".to_vec();
+        torrent.extend_from_slice(info);
+        torrent.push(b'e');
+
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        assert_eq!(metainfo.info_bytes(), info);
+        assert_eq!(metainfo.to_bytes().unwrap(), torrent);
+
+        let from_peer = TorrentMetainfo::from_info_bytes("url".into(), info).unwrap();
+        assert_eq!(from_peer.info_hash, metainfo.info_hash);
+        assert_eq!(from_peer.to_bytes().unwrap(), torrent);
+    }
+
+    #[test]
+    fn parses_announce_list_tiers() {
+        let torrent = b"d13:announce-listll2:t12:t2el2:t3ee4:infod6:lengthi3e4:name3:abc12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = TorrentMetainfo::from_bytes(torrent).unwrap();
+
+        assert_eq!(metainfo.announce, "");
+        assert_eq!(metainfo.tracker_tiers(), vec![vec!["t1", "t2"], vec!["t3"]]);
+        assert_eq!(metainfo.to_bytes().unwrap(), torrent);
+    }
+
+    #[test]
+    fn ignores_malformed_announce_list() {
+        let torrent = b"d8:announce3:url13:announce-listl3:urle4:infod6:lengthi3e4:name3:abc12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = TorrentMetainfo::from_bytes(torrent).unwrap();
+        assert!(metainfo.announce_list.is_empty());
+        assert_eq!(metainfo.tracker_tiers(), vec![vec!["url"]]);
+
+        let torrent = b"d13:announce-listl2:t1li1e2:t2eli3eee4:infod6:lengthi3e4:name3:abc12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = TorrentMetainfo::from_bytes(torrent).unwrap();
+        assert_eq!(metainfo.tracker_tiers(), vec![vec!["t2"]]);
+    }
+
+    fn multi_file_torrent(paths: &str) -> Vec<u8> {
+        format!(
+            "d8:announce3:url4:infod5:filesl{}e4:name3:dir12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee",
+            paths
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn parses_multi_file_layout() {
+        let torrent = multi_file_torrent("d6:lengthi3e4:pathl1:aeed6:lengthi5e4:pathl3:sub1:bee");
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+
+        assert!(metainfo.is_multi_file());
+        assert_eq!(metainfo.name, "dir");
+        assert_eq!(metainfo.length, 8);
+        let files = metainfo.files();
+        assert_eq!(files[1].path, vec!["sub".to_string(), "b".to_string()]);
+        assert_eq!((files[1].offset, files[1].length), (3, 5));
+
+        let reparsed = TorrentMetainfo::from_bytes(&metainfo.to_bytes().unwrap()).unwrap();
+        assert_eq!(reparsed.files(), files);
+        assert_eq!(reparsed.info_hash, metainfo.info_hash);
+    }
+
+    #[test]
+    fn parses_optional_metadata_fields() {
+        let torrent = b"d7:comment5:hello10:created by4:test13:creation datei1700000000e8:encoding5:UTF-84:infod5:filesld6:lengthi3e6:md5sum32:0123456789abcdef0123456789abcdef4:pathl1:aeed6:lengthi0e4:pathl5:emptyeed6:lengthi6e4:pathl1:beee4:name3:dir12:piece lengthi4e6:pieces60:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa6:source3:xyze8:url-list13:http://seed/ae";
+        let metainfo = TorrentMetainfo::from_bytes(torrent).unwrap();
+
+        assert_eq!(metainfo.comment.as_deref(), Some("hello"));
+        assert_eq!(metainfo.created_by.as_deref(), Some("test"));
+        assert_eq!(metainfo.creation_date, Some(1_700_000_000));
+        assert_eq!(metainfo.encoding.as_deref(), Some("UTF-8"));
+        assert_eq!(metainfo.source.as_deref(), Some("xyz"));
+        assert_eq!(metainfo.url_list, vec!["http://seed/a"]);
+        let files = metainfo.files();
+        assert_eq!(
+            files[0].md5sum.as_deref(),
+            Some("0123456789abcdef0123456789abcdef")
+        );
+
+        // a: bytes 0..3, empty: none, b: bytes 3..9
+        assert_eq!(metainfo.piece_range(&files[0]), 0..1);
+        assert!(metainfo.piece_range(&files[1]).is_empty());
+        assert_eq!(metainfo.piece_range(&files[2]), 0..3);
+        assert_eq!(metainfo.pieces_for_files(&[0, 1]).unwrap(), vec![0]);
+        assert_eq!(metainfo.pieces_for_files(&[2, 0]).unwrap(), vec![0, 1, 2]);
+        assert!(metainfo.pieces_for_files(&[3]).is_err());
+
+        assert_eq!(metainfo.to_bytes().unwrap(), torrent);
+    }
+
+    #[test]
+    fn rejects_unsafe_file_paths() {
+        for paths in [
+            "d6:lengthi3e4:pathl2:..6:passwdee",
+            "d6:lengthi3e4:pathl0:1:aee",
+            "d6:lengthi3e4:pathl5:/etc/ee",
+        ] {
+            assert!(TorrentMetainfo::from_bytes(&multi_file_torrent(paths)).is_err());
+        }
+    }
+
+    fn dict<const N: usize>(entries: [(&str, BencodeValue); N]) -> BencodeValue {
+        BencodeValue::from(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.as_bytes().to_vec(), value))
+                .collect::<BTreeMap<_, _>>(),
+        )
+    }
+
+    const V2_PIECE_LENGTH: u64 = 2 * merkle::BLOCK_SIZE;
+
+    /// A v2 torrent holding `dir/big` (two and a bit pieces) and `dir/small` (under a piece),
+    /// with the given piece layers. Returns the torrent and the contents of both files.
+    fn v2_torrent(layers: impl Fn(&[u8]) -> Vec<Hash256>) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        v2_torrent_with(layers, false)
+    }
+
+    /// As [`v2_torrent`], optionally adding the v1 keys of a hybrid torrent: a `files` list
+    /// with a padding file after `big`, and SHA-1 `pieces` over the padded data.
+    fn v2_torrent_with(
+        layers: impl Fn(&[u8]) -> Vec<Hash256>,
+        hybrid: bool,
+    ) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let big = (0..2 * V2_PIECE_LENGTH + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let small = b"small file".to_vec();
+        let file = |data: &[u8]| {
+            dict([(
+                "",
+                dict([
+                    ("length", BencodeValue::from(data.len() as i64)),
+                    (
+                        "pieces root",
+                        BencodeValue::from(&merkle::file_root(data)[..]),
+                    ),
+                ]),
+            )])
+        };
+        let mut info = dict([
+            (
+                "file tree",
+                dict([("big", file(&big)), ("small", file(&small))]),
+            ),
+            ("meta version", BencodeValue::from(2)),
+            ("name", BencodeValue::from("dir")),
+            ("piece length", BencodeValue::from(V2_PIECE_LENGTH as i64)),
+        ]);
+        if hybrid {
+            let pad = 3 * V2_PIECE_LENGTH as usize - big.len();
+            let v1_file = |name: &str, length: usize| {
+                dict([
+                    ("length", BencodeValue::from(length as i64)),
+                    ("path", BencodeValue::from(vec![BencodeValue::from(name)])),
+                ])
+            };
+            let mut padding = v1_file(".pad", pad);
+            if let BencodeValue::Dict(entries) = &mut padding {
+                entries.insert(b"attr".to_vec(), BencodeValue::from("p"));
+            }
+            let data = [&big[..], &vec![0; pad], &small].concat();
+            let pieces = data
+                .chunks(V2_PIECE_LENGTH as usize)
+                .flat_map(utils::sha1)
+                .collect::<Vec<_>>();
+            if let BencodeValue::Dict(entries) = &mut info {
+                entries.insert(
+                    b"files".to_vec(),
+                    BencodeValue::from(vec![
+                        v1_file("big", big.len()),
+                        padding,
+                        v1_file("small", small.len()),
+                    ]),
+                );
+                entries.insert(b"pieces".to_vec(), BencodeValue::from(pieces));
+            }
+        }
+        let torrent = dict([
+            ("announce", BencodeValue::from("url")),
+            ("info", info),
+            (
+                "piece layers",
+                BencodeValue::from(BTreeMap::from([(
+                    merkle::file_root(&big).to_vec(),
+                    BencodeValue::from(layers(&big).concat()),
+                )])),
+            ),
+        ]);
+        (bencode::encode(&torrent), big, small)
+    }
+
+    fn piece_layer(data: &[u8]) -> Vec<Hash256> {
+        data.chunks(V2_PIECE_LENGTH as usize)
+            .map(|piece| merkle::piece_root(piece, V2_PIECE_LENGTH))
+            .collect()
+    }
+
+    #[test]
+    fn parses_v2_file_tree_and_info_hash() {
+        let (torrent, big, _) = v2_torrent(piece_layer);
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+
+        assert_eq!(metainfo.version, MetaVersion::V2);
+        assert!(metainfo.is_multi_file());
+        let hash = utils::sha256(metainfo.info_bytes());
+        assert_eq!(metainfo.info_hash_v2, Some(hash));
+        assert_eq!(metainfo.info_hash, hash[..20]);
+
+        // `small` starts on the piece boundary after `big`.
+        let files = metainfo.files();
+        assert_eq!(files[0].path, vec!["big".to_string()]);
+        assert_eq!(files[0].pieces_root, Some(merkle::file_root(&big)));
+        assert_eq!(files[1].offset, 3 * V2_PIECE_LENGTH);
+        assert_eq!(metainfo.get_piece_count(), 4);
+        assert_eq!(metainfo.piece_len(2), 100);
+        assert_eq!(metainfo.piece_len(3), 10);
+
+        assert_eq!(metainfo.to_bytes().unwrap(), torrent);
+    }
+
+    #[test]
+    fn length_excludes_alignment_gaps_and_padding() {
+        // `big` ends 100 bytes into its last piece, so `small` starts after a gap.
+        let (torrent, big, small) = v2_torrent(piece_layer);
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        assert_eq!(metainfo.length, (big.len() + small.len()) as u64);
+        assert_eq!(
+            metainfo.data_end(),
+            3 * V2_PIECE_LENGTH + small.len() as u64
+        );
+
+        let (torrent, _, _) = v2_torrent_with(piece_layer, true);
+        let hybrid = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        assert_eq!(hybrid.length, metainfo.length);
+        assert_eq!(hybrid.get_piece_count(), metainfo.get_piece_count());
+    }
+
+    #[test]
+    fn verifies_v2_pieces_against_merkle_roots() {
+        let (torrent, big, small) = v2_torrent(piece_layer);
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        let pieces = big.chunks(V2_PIECE_LENGTH as usize).collect::<Vec<_>>();
+
+        for (index, piece) in pieces.iter().enumerate() {
+            metainfo.verify_piece(index as u32, piece).unwrap();
+        }
+        metainfo.verify_piece(3, &small).unwrap();
+        assert!(metainfo.verify_piece(0, pieces[1]).is_err());
+        assert!(metainfo.verify_piece(3, b"smell file").is_err());
+    }
+
+    #[test]
+    fn requires_piece_layers_for_files_larger_than_a_piece() {
+        let (torrent, _, _) = v2_torrent(piece_layer);
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+        metainfo.check_piece_layers(&[0, 1, 2, 3]).unwrap();
+
+        // Metadata from a magnet link has only the info dictionary.
+        let metainfo =
+            TorrentMetainfo::from_info_bytes(String::new(), metainfo.info_bytes()).unwrap();
+        let err = metainfo.check_piece_layers(&[0, 1, 2, 3]).unwrap_err();
+        assert!(err.to_string().contains("piece layers required"));
+        // The small file fits in one piece and is verified against its root alone.
+        metainfo.check_piece_layers(&[3]).unwrap();
+    }
+
+    #[test]
+    fn rejects_piece_layers_not_matching_the_root() {
+        let (torrent, _, _) = v2_torrent(|data| {
+            let mut layer = piece_layer(data);
+            layer[1][0] ^= 1;
+            layer
+        });
+        assert!(TorrentMetainfo::from_bytes(&torrent).is_err());
+
+        let (torrent, _, _) = v2_torrent(|data| piece_layer(data)[..2].to_vec());
+        assert!(TorrentMetainfo::from_bytes(&torrent).is_err());
+    }
+
+    #[test]
+    fn hybrid_torrent_has_both_hashes_and_verifies_both() {
+        let (torrent, big, small) = v2_torrent_with(piece_layer, true);
+        let metainfo = TorrentMetainfo::from_bytes(&torrent).unwrap();
+
+        assert_eq!(metainfo.version, MetaVersion::Hybrid);
+        let sha1 = utils::sha1(metainfo.info_bytes());
+        let sha256 = utils::sha256(metainfo.info_bytes());
+        assert_eq!(metainfo.info_hash, sha1);
+        assert_eq!(metainfo.info_hash_v2, Some(sha256));
+        assert_eq!(metainfo.swarm_hashes(), vec![sha1, sha256[..20].to_vec()]);
+        assert!(metainfo.has_info_hash(&sha256));
+
+        // The padding file is part of the layout, and v1 pieces include its zeros.
+        let files = metainfo.files();
+        assert!(files[1].padding);
+        assert_eq!(files[2].pieces_root, Some(merkle::file_root(&small)));
+        assert_eq!(metainfo.get_piece_count(), 4);
+        assert_eq!(metainfo.piece_len(2), V2_PIECE_LENGTH);
+
+        let mut last_of_big = big[2 * V2_PIECE_LENGTH as usize..].to_vec();
+        last_of_big.resize(V2_PIECE_LENGTH as usize, 0);
+        metainfo.verify_piece(2, &last_of_big).unwrap();
+        metainfo.verify_piece(3, &small).unwrap();
+
+        // Garbage in the padding only breaks the v1 hash.
+        *last_of_big.last_mut().unwrap() = 1;
+        assert!(metainfo.verify_piece(2, &last_of_big).is_err());
+    }
+
+    #[test]
+    fn rejects_hybrid_torrent_with_mismatched_file_lists() {
+        let (mut torrent, _, _) = v2_torrent_with(piece_layer, true);
+        let v1_path = b"4:pathl5:smallee";
+        let at = torrent
+            .windows(v1_path.len())
+            .position(|window| window == v1_path)
+            .unwrap();
+        torrent[at..at + v1_path.len()].copy_from_slice(b"4:pathl5:smellee");
+        assert!(TorrentMetainfo::from_bytes(&torrent).is_err());
+    }
 }
@@ -1,5 +1,15 @@
+mod builder;
+mod cache;
+mod edit;
+mod files;
+pub mod lint;
 mod magnet;
+mod merkle;
 mod metainfo;
 
-pub use magnet::MagnetLink;
-pub use metainfo::TorrentMetainfo;
+pub use builder::TorrentBuilder;
+pub use cache::MetadataCache;
+pub use edit::TorrentEdit;
+pub use files::TorrentFile;
+pub use magnet::{MagnetLink, PeerAddress};
+pub use metainfo::{MetaVersion, TorrentMetainfo};
@@ -1,11 +1,13 @@
 use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs};
 use std::str::FromStr;
+use std::time::Duration;
 
 use reqwest;
 
-use crate::utils::RawBytesExt;
+use super::UdpTracker;
 use crate::{bencode, utils};
 
+#[derive(Debug, Clone)]
 pub struct TrackerRequest {
     pub info_hash: Vec<u8>,
     pub peer_id: String,
@@ -16,6 +18,7 @@ pub struct TrackerRequest {
     pub compact: u8,
 }
 
+#[derive(Debug)]
 pub struct TrackerResponse {
     pub interval: u32,
     pub peers: Vec<Peer>,
@@ -53,7 +56,31 @@ impl std::fmt::Display for Peer {
     }
 }
 
+/// Announce to the tracker at `announce_url`, over UDP (BEP-15) for `udp://` URLs and
+/// HTTP otherwise. A silent UDP tracker is retried on the spec's full schedule.
 pub fn announce(announce_url: String, request: TrackerRequest) -> anyhow::Result<TrackerResponse> {
+    announce_with_udp_timeouts(
+        announce_url,
+        request,
+        UdpTracker::BASE_TIMEOUT,
+        UdpTracker::MAX_RETRIES,
+    )
+}
+
+/// Like [`announce`], giving up on a UDP tracker after `max_retries` retransmissions
+/// (see [`UdpTracker::with_timeouts`]). For callers with other trackers to fall back on.
+pub fn announce_with_udp_timeouts(
+    announce_url: String,
+    request: TrackerRequest,
+    base_timeout: Duration,
+    max_retries: u32,
+) -> anyhow::Result<TrackerResponse> {
+    if announce_url.starts_with("udp://") {
+        return UdpTracker::new(&announce_url)?
+            .with_timeouts(base_timeout, max_retries)
+            .announce(&request);
+    }
+
     let url = format!(
         "{}?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&compact={}",
         announce_url,
@@ -67,7 +94,8 @@ pub fn announce(announce_url: String, request: TrackerRequest) -> anyhow::Result
     );
 
     let response = reqwest::blocking::get(&url)?.bytes()?;
-    let parsed_response = bencode::parse_bytes(response.to_vec());
+    let parsed_response =
+        bencode::parse_bytes_limited(response.to_vec(), bencode::DecodeLimits::untrusted())?;
 
     let result = TrackerResponse {
         interval: parsed_response
@@ -76,21 +104,26 @@ pub fn announce(announce_url: String, request: TrackerRequest) -> anyhow::Result
             .unwrap_or(0) as u32,
         peers: parsed_response
             .get("peers")
-            .and_then(|v| v.as_str())
+            .and_then(|v| v.as_bytes())
             .map(parse_peers)
-            .unwrap_or(vec![]),
+            .transpose()?
+            .unwrap_or_default(),
     };
     Ok(result)
 }
 
-fn parse_peers(peers: &str) -> Vec<Peer> {
-    let bytes = peers.to_raw_bytes();
+/// Parse a compact peer list: 6 bytes per peer. A list that is not a whole number of peers
+/// means the response is malformed, and it is rejected rather than trusted in part.
+pub(super) fn parse_peers(peers: &[u8]) -> anyhow::Result<Vec<Peer>> {
+    if !peers.len().is_multiple_of(6) {
+        anyhow::bail!(
+            "Invalid compact peers string of {} bytes, not a multiple of 6",
+            peers.len()
+        );
+    }
     let mut result = Vec::new();
 
-    for chunk in bytes.chunks(6) {
-        if chunk.len() < 6 {
-            panic!("Invalid peers binary string");
-        }
+    for chunk in peers.chunks(6) {
         // First 4 bytes are IP address, last 2 bytes are port
         result.push(Peer {
             ip: Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]),
@@ -98,19 +131,17 @@ fn parse_peers(peers: &str) -> Vec<Peer> {
         });
     }
 
-    result
+    Ok(result)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::RawStringExt;
-
     use super::*;
 
     #[test]
     fn parse_peers_valid() {
         let peers_bytes = b"\x7F\x00\x00\x01\x1A\xE1\xC0\xA8\x01\x68\x1A\xE2";
-        let peers = parse_peers(peers_bytes.to_raw_string().as_str());
+        let peers = parse_peers(peers_bytes).unwrap();
 
         assert_eq!(peers.len(), 2);
         assert_eq!(peers[0].ip, Ipv4Addr::new(127, 0, 0, 1));
@@ -120,9 +151,9 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Invalid peers binary string")]
-    fn parse_peers_panics_on_invalid_length() {
+    fn parse_peers_rejects_invalid_length() {
         let peers_bytes = b"\x7F\x00\x00\x01\x1A";
-        parse_peers(peers_bytes.to_raw_string().as_str());
+        let error = parse_peers(peers_bytes).unwrap_err();
+        assert!(error.to_string().contains("not a multiple of 6"));
     }
 }
@@ -1,3 +1,7 @@
 mod client;
+mod tiers;
+mod udp;
 
-pub use client::{announce, Peer, TrackerRequest, TrackerResponse};
+pub use client::{announce, announce_with_udp_timeouts, Peer, TrackerRequest, TrackerResponse};
+pub use tiers::TrackerTiers;
+pub use udp::{scrape, ScrapeStats, UdpTracker};
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use rand::seq::SliceRandom;
+
+use super::{announce_with_udp_timeouts, TrackerRequest, TrackerResponse, UdpTracker};
+use crate::log_debug;
+
+/// Trackers grouped into tiers, as described by BEP-12 (`announce-list`).
+///
+/// Tiers are tried in order and the trackers within a tier in their current order. The
+/// tracker that answers is moved to the front of its tier so it is tried first next time.
+/// A silent UDP tracker is given up on after [`UdpTracker::FALLBACK_RETRIES`] rather than
+/// BEP-15's full schedule, so the next tracker gets its turn within a minute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackerTiers {
+    tiers: Vec<Vec<String>>,
+    udp_timeouts: (Duration, u32),
+}
+
+impl TrackerTiers {
+    /// Build tiers from `announce-list`, shuffling each tier once as the spec requires.
+    /// Empty tiers are dropped.
+    pub fn new(tiers: Vec<Vec<String>>) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut tiers = Self::ordered(tiers);
+        for tier in &mut tiers.tiers {
+            tier.shuffle(&mut rng);
+        }
+        tiers
+    }
+
+    /// Build tiers keeping the given order within each tier.
+    pub fn ordered(tiers: Vec<Vec<String>>) -> Self {
+        Self {
+            tiers: tiers.into_iter().filter(|tier| !tier.is_empty()).collect(),
+            udp_timeouts: (UdpTracker::BASE_TIMEOUT, UdpTracker::FALLBACK_RETRIES),
+        }
+    }
+
+    /// Wait `base * 2^n` before the n-th retransmission to a UDP tracker, moving on to the
+    /// next tracker after `max_retries`.
+    pub fn with_udp_timeouts(mut self, base: Duration, max_retries: u32) -> Self {
+        self.udp_timeouts = (base, max_retries);
+        self
+    }
+
+    pub fn tiers(&self) -> &[Vec<String>] {
+        &self.tiers
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiers.is_empty()
+    }
+
+    /// Announce to the first tracker that responds.
+    pub fn announce(&mut self, request: &TrackerRequest) -> anyhow::Result<TrackerResponse> {
+        let (base, max_retries) = self.udp_timeouts;
+        self.announce_with(request, |url, request| {
+            announce_with_udp_timeouts(url.to_string(), request.clone(), base, max_retries)
+        })
+    }
+
+    fn announce_with<F>(
+        &mut self,
+        request: &TrackerRequest,
+        mut announce: F,
+    ) -> anyhow::Result<TrackerResponse>
+    where
+        F: FnMut(&str, &TrackerRequest) -> anyhow::Result<TrackerResponse>,
+    {
+        let mut last_error = None;
+        for tier in &mut self.tiers {
+            for index in 0..tier.len() {
+                match announce(&tier[index], request) {
+                    Ok(response) => {
+                        tier[..=index].rotate_right(1);
+                        return Ok(response);
+                    }
+                    Err(e) => {
+                        log_debug!("TrackerTiers", "Tracker {} failed: {}", tier[index], e);
+                        last_error = Some(e);
+                    }
+                }
+            }
+        }
+
+        Err(match last_error {
+            Some(e) => e.context("All trackers failed"),
+            None => anyhow!("No trackers to announce to"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> TrackerRequest {
+        TrackerRequest {
+            info_hash: vec![0; 20],
+            peer_id: "-CT0001-123456789012".to_string(),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 0,
+            compact: 1,
+        }
+    }
+
+    fn tiers(tiers: &[&[&str]]) -> Vec<Vec<String>> {
+        tiers
+            .iter()
+            .map(|tier| tier.iter().map(|url| url.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn tries_tiers_in_order_and_promotes_the_responding_tracker() {
+        let mut trackers = TrackerTiers::ordered(tiers(&[&["a", "b", "c"], &[], &["d"]]));
+        let mut attempts = Vec::new();
+
+        let response = trackers.announce_with(&request(), |url, _| {
+            attempts.push(url.to_string());
+            if url == "c" {
+                Ok(TrackerResponse {
+                    interval: 60,
+                    peers: vec![],
+                })
+            } else {
+                Err(anyhow!("down"))
+            }
+        });
+
+        assert_eq!(response.unwrap().interval, 60);
+        assert_eq!(attempts, ["a", "b", "c"]);
+        assert_eq!(trackers.tiers(), tiers(&[&["c", "a", "b"], &["d"]]));
+    }
+
+    #[test]
+    fn fails_when_every_tracker_fails() {
+        let mut trackers = TrackerTiers::ordered(tiers(&[&["a"], &["b"]]));
+        let mut attempts = 0;
+        let result = trackers.announce_with(&request(), |_, _| {
+            attempts += 1;
+            Err(anyhow!("down"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 2);
+        assert!(TrackerTiers::new(vec![vec![]])
+            .announce(&request())
+            .is_err());
+    }
+
+    #[test]
+    fn falls_through_a_silent_udp_tracker_to_the_next_tier() {
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let url = format!("udp://{}/announce", silent.local_addr().unwrap());
+        let mut trackers = TrackerTiers::ordered(vec![vec![url], vec!["next".to_string()]])
+            .with_udp_timeouts(Duration::from_millis(20), 1);
+
+        // Two tries of 20 and 40 ms, then the next tier: the HTTP announce to "next" fails
+        // at once on its bad URL.
+        let started = std::time::Instant::now();
+        let error = trackers.announce(&request()).unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(format!("{error:#}").contains("All trackers failed"));
+
+        let mut buf = [0u8; 64];
+        silent.set_nonblocking(true).unwrap();
+        let mut datagrams = 0;
+        while silent.recv(&mut buf).is_ok() {
+            datagrams += 1;
+        }
+        assert_eq!(datagrams, 2);
+    }
+
+    #[test]
+    fn shuffles_only_within_tiers() {
+        let trackers = TrackerTiers::new(tiers(&[&["a", "b", "c"], &["d"]]));
+        let mut first = trackers.tiers()[0].clone();
+        first.sort();
+        assert_eq!(first, ["a", "b", "c"]);
+        assert_eq!(trackers.tiers()[1], ["d"]);
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use reqwest::Url;
+
+use super::client::parse_peers;
+use super::{TrackerRequest, TrackerResponse};
+use crate::log_debug;
+
+/// Magic constant that starts every connect request.
+const PROTOCOL_ID: u64 = 0x41727101980;
+
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+
+/// How long a connection ID may be used after the tracker hands it out.
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Connection IDs by tracker address, shared by every [`UdpTracker`] in the process so
+/// back-to-back announces do not each need a connect round trip.
+fn connections() -> &'static Mutex<HashMap<SocketAddr, (u64, Instant)>> {
+    static CONNECTIONS: OnceLock<Mutex<HashMap<SocketAddr, (u64, Instant)>>> = OnceLock::new();
+    CONNECTIONS.get_or_init(Default::default)
+}
+
+/// Swarm statistics for one info hash, from a scrape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrapeStats {
+    pub seeders: u32,
+    pub completed: u32,
+    pub leechers: u32,
+}
+
+/// Client for a `udp://` tracker (BEP-15).
+///
+/// Every announce or scrape needs a connection ID from a connect exchange first; IDs are
+/// cached per tracker until they expire. Requests are matched to responses by a random
+/// transaction ID and retransmitted with the spec's doubling timeouts. The retry budget
+/// covers the whole exchange, connect included.
+pub struct UdpTracker {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    base_timeout: Duration,
+    max_retries: u32,
+}
+
+impl UdpTracker {
+    /// A request is retransmitted after `15 * 2^n` seconds.
+    pub const BASE_TIMEOUT: Duration = Duration::from_secs(15);
+    /// BEP-15 keeps retransmitting up to n = 8, about an hour and a half in all; only
+    /// worth it when there is no other tracker to try.
+    pub const MAX_RETRIES: u32 = 8;
+    /// Retries when other trackers can be tried instead: give up after 15 + 30 seconds.
+    pub const FALLBACK_RETRIES: u32 = 1;
+
+    /// Bind a socket for talking to the tracker at `url` (`udp://host:port[/path]`).
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        let url = Url::parse(url).context("invalid tracker URL")?;
+        if url.scheme() != "udp" {
+            bail!("expected udp: scheme, got {}", url.scheme());
+        }
+        let host = url.host_str().context("tracker URL has no host")?;
+        let port = url.port().context("UDP tracker URL has no port")?;
+        // Peers come back in the compact IPv4 format, so talk to the tracker over IPv4.
+        let addr = (host, port)
+            .to_socket_addrs()
+            .with_context(|| format!("cannot resolve tracker {}", host))?
+            .find(SocketAddr::is_ipv4)
+            .with_context(|| format!("tracker {} has no IPv4 address", host))?;
+
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.connect(addr)?;
+        Ok(Self {
+            socket,
+            addr,
+            base_timeout: Self::BASE_TIMEOUT,
+            max_retries: Self::MAX_RETRIES,
+        })
+    }
+
+    /// Wait `base * 2^n` before the n-th retransmission, giving up after `max_retries`.
+    pub fn with_timeouts(mut self, base: Duration, max_retries: u32) -> Self {
+        self.base_timeout = base;
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn announce(&self, request: &TrackerRequest) -> anyhow::Result<TrackerResponse> {
+        if request.info_hash.len() != 20 || request.peer_id.len() != 20 {
+            bail!("info hash and peer id must be 20 bytes");
+        }
+        let mut body = Vec::with_capacity(82);
+        body.extend_from_slice(&request.info_hash);
+        body.extend_from_slice(request.peer_id.as_bytes());
+        body.extend_from_slice(&request.downloaded.to_be_bytes());
+        body.extend_from_slice(&request.left.to_be_bytes());
+        body.extend_from_slice(&request.uploaded.to_be_bytes());
+        body.extend_from_slice(&0u32.to_be_bytes()); // event: none
+        body.extend_from_slice(&0u32.to_be_bytes()); // ip: the sender's
+        body.extend_from_slice(&rand::random::<u32>().to_be_bytes()); // key
+        body.extend_from_slice(&(-1i32).to_be_bytes()); // num_want: default
+        body.extend_from_slice(&request.port.to_be_bytes());
+
+        let response = self.request(ACTION_ANNOUNCE, &body)?;
+        // interval, leechers, seeders, then compact peers
+        if response.len() < 12 {
+            bail!("malformed announce response of {} bytes", response.len());
+        }
+        Ok(TrackerResponse {
+            interval: read_u32(&response, 0),
+            peers: parse_peers(&response[12..])?,
+        })
+    }
+
+    /// Swarm statistics for each of `info_hashes`, in the same order.
+    pub fn scrape(&self, info_hashes: &[Vec<u8>]) -> anyhow::Result<Vec<ScrapeStats>> {
+        if info_hashes.iter().any(|hash| hash.len() != 20) {
+            bail!("info hashes must be 20 bytes");
+        }
+        let response = self.request(ACTION_SCRAPE, &info_hashes.concat())?;
+        if response.len() != info_hashes.len() * 12 {
+            bail!(
+                "scrape response has {} bytes for {} info hashes",
+                response.len(),
+                info_hashes.len()
+            );
+        }
+        Ok(response
+            .chunks(12)
+            .map(|chunk| ScrapeStats {
+                seeders: read_u32(chunk, 0),
+                completed: read_u32(chunk, 4),
+                leechers: read_u32(chunk, 8),
+            })
+            .collect())
+    }
+
+    /// Send an announce or scrape and return the response after its action and transaction
+    /// ID. A connection ID that expires while retransmitting is renewed. Connect and request
+    /// share one budget of `max_retries` retransmissions, so a silent tracker costs at most
+    /// `base * (2^(max_retries + 1) - 1)`.
+    fn request(&self, action: u32, body: &[u8]) -> anyhow::Result<Vec<u8>> {
+        for attempt in 0..=self.max_retries {
+            let Some(connection_id) = self.connection_id(attempt)? else {
+                continue;
+            };
+            let mut packet = connection_id.to_be_bytes().to_vec();
+            packet.extend_from_slice(&action.to_be_bytes());
+            packet.extend_from_slice(&[0; 4]);
+            packet.extend_from_slice(body);
+
+            if let Some(response) = self.exchange(&mut packet, action, attempt)? {
+                return Ok(response);
+            }
+        }
+        bail!("tracker {} did not respond", self.addr)
+    }
+
+    /// The cached connection ID, or a new one from a connect exchange waiting as long as
+    /// retry `attempt` does. `None` if the tracker did not answer in time.
+    fn connection_id(&self, attempt: u32) -> anyhow::Result<Option<u64>> {
+        if let Some(&(id, obtained)) = connections().lock().unwrap().get(&self.addr) {
+            if obtained.elapsed() < CONNECTION_ID_LIFETIME {
+                return Ok(Some(id));
+            }
+        }
+
+        let mut packet = PROTOCOL_ID.to_be_bytes().to_vec();
+        packet.extend_from_slice(&ACTION_CONNECT.to_be_bytes());
+        packet.extend_from_slice(&[0; 4]);
+        let Some(response) = self.exchange(&mut packet, ACTION_CONNECT, attempt)? else {
+            return Ok(None);
+        };
+        if response.len() < 8 {
+            bail!("malformed connect response");
+        }
+        let id = u64::from_be_bytes(response[..8].try_into().unwrap());
+        connections()
+            .lock()
+            .unwrap()
+            .insert(self.addr, (id, Instant::now()));
+        Ok(Some(id))
+    }
+
+    /// Send `packet` (whose bytes 12..16 are filled with a fresh transaction ID) and wait
+    /// for the matching response for `base * 2^attempt`. `None` on timeout; packets for
+    /// other transactions are ignored.
+    fn exchange(
+        &self,
+        packet: &mut [u8],
+        action: u32,
+        attempt: u32,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let transaction_id = rand::random::<u32>();
+        packet[12..16].copy_from_slice(&transaction_id.to_be_bytes());
+        self.socket.send(packet)?;
+
+        let deadline = Instant::now() + self.base_timeout * 2u32.pow(attempt);
+        let mut buf = [0u8; 2048];
+        loop {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                log_debug!(
+                    "UdpTracker",
+                    "No response from {} (try {})",
+                    self.addr,
+                    attempt
+                );
+                return Ok(None);
+            };
+            self.socket
+                .set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+            let len = match self.socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(e) => return Err(e).with_context(|| format!("tracker {}", self.addr)),
+            };
+
+            let response = &buf[..len];
+            if len < 8 || read_u32(response, 4) != transaction_id {
+                continue;
+            }
+            match read_u32(response, 0) {
+                ACTION_ERROR => {
+                    let message = String::from_utf8_lossy(&response[8..]);
+                    return Err(anyhow!("tracker error: {}", message));
+                }
+                a if a == action => return Ok(Some(response[8..].to_vec())),
+                other => bail!("unexpected action {} in tracker response", other),
+            }
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Scrape a `udp://` tracker with the spec's timeouts.
+pub fn scrape(url: &str, info_hashes: &[Vec<u8>]) -> anyhow::Result<Vec<ScrapeStats>> {
+    UdpTracker::new(url)?.scrape(info_hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// A tracker stand-in on loopback. `reply` gets each datagram and the number of connect
+    /// requests seen so far, and returns the reply to send, if any.
+    fn stand_in<F>(mut reply: F) -> (String, Arc<AtomicUsize>)
+    where
+        F: FnMut(&[u8], usize) -> Option<Vec<u8>> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let url = format!("udp://{}/announce", socket.local_addr().unwrap());
+        let connects = Arc::new(AtomicUsize::new(0));
+        let seen = connects.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 2048];
+            while let Ok((len, from)) = socket.recv_from(&mut buf) {
+                let packet = &buf[..len];
+                if packet[..8] == PROTOCOL_ID.to_be_bytes() {
+                    seen.fetch_add(1, Ordering::SeqCst);
+                }
+                if let Some(response) = reply(packet, seen.load(Ordering::SeqCst)) {
+                    socket.send_to(&response, from).unwrap();
+                }
+            }
+        });
+        (url, connects)
+    }
+
+    /// Answer connects with connection ID 7 and announces with one peer.
+    fn tracker(packet: &[u8], _: usize) -> Option<Vec<u8>> {
+        let action = read_u32(packet, 8);
+        let mut response = packet[8..16].to_vec();
+        match action {
+            ACTION_CONNECT => response.extend_from_slice(&7u64.to_be_bytes()),
+            ACTION_ANNOUNCE => {
+                assert_eq!(&packet[..8], &7u64.to_be_bytes());
+                assert_eq!(packet.len(), 98);
+                assert_eq!(&packet[16..36], &[1; 20]);
+                response.extend_from_slice(&1800u32.to_be_bytes());
+                response.extend_from_slice(&0u32.to_be_bytes());
+                response.extend_from_slice(&1u32.to_be_bytes());
+                response.extend_from_slice(&[127, 0, 0, 1, 0x1A, 0xE1]);
+            }
+            ACTION_SCRAPE => {
+                for _ in packet[16..].chunks(20) {
+                    for value in [5u32, 10, 2] {
+                        response.extend_from_slice(&value.to_be_bytes());
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        Some(response)
+    }
+
+    fn request() -> TrackerRequest {
+        TrackerRequest {
+            info_hash: vec![1; 20],
+            peer_id: "-CT0001-123456789012".to_string(),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            compact: 1,
+        }
+    }
+
+    #[test]
+    fn announces_and_scrapes_reusing_the_connection_id() {
+        let (url, connects) = stand_in(tracker);
+
+        let response = UdpTracker::new(&url).unwrap().announce(&request()).unwrap();
+        assert_eq!(response.interval, 1800);
+        assert_eq!(response.peers.len(), 1);
+        assert_eq!(response.peers[0].to_string(), "127.0.0.1:6881");
+
+        let stats = scrape(&url, &[vec![1; 20], vec![2; 20]]).unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                ScrapeStats {
+                    seeders: 5,
+                    completed: 10,
+                    leechers: 2
+                };
+                2
+            ]
+        );
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn retransmits_after_a_timeout() {
+        // Drop the first connect request; answer everything after it.
+        let (url, connects) = stand_in(|packet, connects| match connects {
+            1 => None,
+            _ => tracker(packet, connects),
+        });
+        let tracker = UdpTracker::new(&url)
+            .unwrap()
+            .with_timeouts(Duration::from_millis(50), 2);
+
+        assert_eq!(tracker.announce(&request()).unwrap().peers.len(), 1);
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn reports_tracker_errors_and_silence() {
+        let (url, _) = stand_in(|packet, _| {
+            let mut response = ACTION_ERROR.to_be_bytes().to_vec();
+            response.extend_from_slice(&packet[12..16]);
+            response.extend_from_slice(b"unknown torrent");
+            Some(response)
+        });
+        let error = UdpTracker::new(&url)
+            .unwrap()
+            .announce(&request())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "tracker error: unknown torrent");
+
+        let (url, _) = stand_in(|_, _| None);
+        let tracker = UdpTracker::new(&url)
+            .unwrap()
+            .with_timeouts(Duration::from_millis(10), 1);
+        assert!(tracker.announce(&request()).is_err());
+    }
+}
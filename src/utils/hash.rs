@@ -1,7 +1,12 @@
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
 
 pub fn sha1(data: &[u8]) -> Vec<u8> {
     let mut hasher = Sha1::new();
     hasher.update(data);
     hasher.finalize().to_vec()
 }
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
@@ -1,12 +1,14 @@
 pub mod bytes;
 pub mod hash;
 pub mod log;
+pub mod time;
 pub mod url;
 
 pub use bytes::{RawBytesExt, RawStringExt};
-pub use hash::sha1;
+pub use hash::{sha1, sha256};
 pub use log::{
 	set_global_log_handler, set_global_log_level,
 	ConsoleLogger, LogHandler, LogLevel,
 };
+pub use time::format_unix_time;
 pub use url::url_encode;
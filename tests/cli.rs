@@ -0,0 +1,29 @@
+use std::process::Command;
+
+fn decode(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_codecrafters-bittorrent"))
+        .arg("decode")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn decode_rejects_unknown_options_without_panicking() {
+    let output = decode(&["--binary", "base32", "i1e"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("unknown binary encoding 'base32'"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("panicked"), "{stderr}");
+
+    let output = decode(&["--format", "yaml", "i1e"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unknown format: yaml"), "{stderr}");
+
+    let output = decode(&["--binary", "base64", "i1e"]);
+    assert!(output.status.success());
+}